@@ -0,0 +1,4 @@
+// Recompile when migrations are added, they are embedded by sqlx::migrate!
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,20 @@
+-- PostgreSQL starts out with the current schema of the SQLite migrations
+CREATE TABLE suspensions (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    moderator_id BIGINT NOT NULL,
+    from_datetime TIMESTAMPTZ NOT NULL,
+    until_datetime TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    active BOOLEAN NOT NULL
+);
+
+CREATE TABLE suspension_roles (
+    suspension_id BIGINT NOT NULL REFERENCES suspensions(id) ON DELETE CASCADE,
+    role_id BIGINT NOT NULL,
+    role_name TEXT,
+    PRIMARY KEY (suspension_id, role_id)
+);
+
+CREATE INDEX suspension_roles_role_id ON suspension_roles (role_id);
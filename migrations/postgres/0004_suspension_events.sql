@@ -0,0 +1,21 @@
+-- Append-only log of everything that happened to a suspension
+CREATE TABLE suspension_events (
+    id BIGSERIAL PRIMARY KEY,
+    suspension_id BIGINT NOT NULL REFERENCES suspensions(id),
+    action TEXT NOT NULL,
+    actor_id BIGINT,
+    created_at TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    source TEXT NOT NULL
+);
+
+CREATE INDEX suspension_events_suspension_id ON suspension_events (suspension_id);
+
+CREATE FUNCTION reject_suspension_event_change() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'suspension_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER suspension_events_append_only BEFORE UPDATE OR DELETE ON suspension_events
+    FOR EACH ROW EXECUTE FUNCTION reject_suspension_event_change();
@@ -0,0 +1,31 @@
+-- Retention and erasure may remove personal data from the event log, nothing else may change
+CREATE OR REPLACE FUNCTION reject_suspension_event_change() RETURNS trigger AS $$
+DECLARE
+    suspension_active BOOLEAN;
+BEGIN
+    SELECT active INTO suspension_active FROM suspensions WHERE id = OLD.suspension_id;
+
+    -- Events of ended suspensions are deleted together with the suspension
+    IF TG_OP = 'DELETE' THEN
+        IF suspension_active THEN
+            RAISE EXCEPTION 'suspension_events is append-only';
+        END IF;
+
+        RETURN OLD;
+    END IF;
+
+    -- Actors can always be pseudonymized to 0, reasons can be cleared once the suspension has ended
+    IF NEW.id = OLD.id
+        AND NEW.suspension_id = OLD.suspension_id
+        AND NEW.action = OLD.action
+        AND NEW.created_at = OLD.created_at
+        AND NEW.source = OLD.source
+        AND (NEW.actor_id IS NOT DISTINCT FROM OLD.actor_id OR NEW.actor_id = 0)
+        AND (NEW.reason IS NOT DISTINCT FROM OLD.reason OR (NEW.reason IS NULL AND suspension_active IS NOT TRUE))
+    THEN
+        RETURN NEW;
+    END IF;
+
+    RAISE EXCEPTION 'suspension_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
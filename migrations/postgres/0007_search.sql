@@ -0,0 +1,5 @@
+-- Full-text index over the reasons of suspensions, the simple configuration works for every language
+ALTER TABLE suspensions ADD COLUMN search TSVECTOR
+    GENERATED ALWAYS AS (to_tsvector('simple', coalesce(reason, '') || ' ' || coalesce(lift_reason, ''))) STORED;
+
+CREATE INDEX suspensions_search ON suspensions USING GIN (search);
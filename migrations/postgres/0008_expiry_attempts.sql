@@ -0,0 +1,3 @@
+-- How often ending a suspension failed and when the monitor tries again
+ALTER TABLE suspensions ADD COLUMN expiry_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE suspensions ADD COLUMN next_attempt_at TIMESTAMPTZ;
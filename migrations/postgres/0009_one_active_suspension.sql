@@ -0,0 +1,18 @@
+-- Two /suspend commands at the same time could both succeed, the later one only removed the suspended role
+-- Keep the earliest active suspension of a user and lift the duplicates
+WITH duplicates AS (
+    UPDATE suspensions SET
+        active = FALSE,
+        lifted_at = NOW(),
+        lift_reason = 'Duplicate of an earlier active suspension'
+    WHERE active = TRUE AND EXISTS (
+        SELECT 1 FROM suspensions AS earlier
+        WHERE earlier.guild_id = suspensions.guild_id AND earlier.user_id = suspensions.user_id AND earlier.active = TRUE AND earlier.id < suspensions.id
+    )
+    RETURNING id, lifted_at, lift_reason
+)
+INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+SELECT id, 'lift', NULL, lifted_at, lift_reason, 'migration' FROM duplicates;
+
+-- From now on a user can only have one active suspension per guild
+CREATE UNIQUE INDEX suspensions_one_active ON suspensions (guild_id, user_id) WHERE active;
@@ -0,0 +1,7 @@
+-- Reminders sent to staff before a suspension ends, so none is sent twice
+CREATE TABLE suspension_reminders (
+    suspension_id BIGINT NOT NULL REFERENCES suspensions(id) ON DELETE CASCADE,
+    seconds_before_expiry BIGINT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (suspension_id, seconds_before_expiry)
+);
@@ -0,0 +1,12 @@
+-- Initial schema, existing databases already have this table
+CREATE TABLE IF NOT EXISTS suspensions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    moderator_id INTEGER NOT NULL,
+    previous_roles TEXT NOT NULL,
+    from_datetime TEXT NOT NULL,
+    until_datetime TEXT NOT NULL,
+    reason TEXT,
+    active BOOLEAN NOT NULL
+);
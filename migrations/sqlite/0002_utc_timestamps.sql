@@ -0,0 +1,5 @@
+-- Timestamps used to be naive local time, convert them to UTC (RFC 3339)
+-- The 'utc' modifier uses the timezone of the host running the migration
+UPDATE suspensions SET
+    from_datetime = strftime('%Y-%m-%dT%H:%M:%S+00:00', from_datetime, 'utc'),
+    until_datetime = strftime('%Y-%m-%dT%H:%M:%S+00:00', until_datetime, 'utc');
@@ -0,0 +1,21 @@
+-- Removed roles get their own table instead of a comma-separated column
+CREATE TABLE suspension_roles (
+    suspension_id INTEGER NOT NULL REFERENCES suspensions(id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL,
+    role_name TEXT,
+    PRIMARY KEY (suspension_id, role_id)
+);
+
+CREATE INDEX suspension_roles_role_id ON suspension_roles (role_id);
+
+-- Split the old comma-separated lists, role names are unknown for these
+WITH RECURSIVE split(suspension_id, role_id, rest) AS (
+    SELECT id, '', previous_roles || ',' FROM suspensions
+    UNION ALL
+    SELECT suspension_id, substr(rest, 1, instr(rest, ',') - 1), substr(rest, instr(rest, ',') + 1)
+    FROM split WHERE rest <> ''
+)
+INSERT OR IGNORE INTO suspension_roles (suspension_id, role_id)
+SELECT suspension_id, CAST(role_id AS INTEGER) FROM split WHERE role_id <> '';
+
+ALTER TABLE suspensions DROP COLUMN previous_roles;
@@ -0,0 +1,26 @@
+-- Append-only log of everything that happened to a suspension
+CREATE TABLE suspension_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    suspension_id INTEGER NOT NULL REFERENCES suspensions(id),
+    action TEXT NOT NULL,
+    actor_id INTEGER,
+    created_at TEXT NOT NULL,
+    reason TEXT,
+    source TEXT NOT NULL
+);
+
+CREATE INDEX suspension_events_suspension_id ON suspension_events (suspension_id);
+
+CREATE TRIGGER suspension_events_no_update BEFORE UPDATE ON suspension_events
+BEGIN
+    SELECT RAISE(ABORT, 'suspension_events is append-only');
+END;
+
+CREATE TRIGGER suspension_events_no_delete BEFORE DELETE ON suspension_events
+BEGIN
+    SELECT RAISE(ABORT, 'suspension_events is append-only');
+END;
+
+-- Existing suspensions only have a known creation
+INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+SELECT id, 'create', moderator_id, from_datetime, reason, 'command' FROM suspensions;
@@ -0,0 +1,11 @@
+-- Who lifted a suspension early, when and why
+ALTER TABLE suspensions ADD COLUMN lifted_by INTEGER;
+ALTER TABLE suspensions ADD COLUMN lifted_at TEXT;
+ALTER TABLE suspensions ADD COLUMN lift_reason TEXT;
+
+-- Lifts recorded in the event log so far
+UPDATE suspensions SET
+    lifted_by = (SELECT actor_id FROM suspension_events WHERE suspension_events.suspension_id = suspensions.id AND action = 'lift'),
+    lifted_at = (SELECT created_at FROM suspension_events WHERE suspension_events.suspension_id = suspensions.id AND action = 'lift'),
+    lift_reason = (SELECT reason FROM suspension_events WHERE suspension_events.suspension_id = suspensions.id AND action = 'lift')
+WHERE EXISTS (SELECT 1 FROM suspension_events WHERE suspension_events.suspension_id = suspensions.id AND action = 'lift');
@@ -0,0 +1,25 @@
+-- Retention and erasure may remove personal data from the event log, nothing else may change
+DROP TRIGGER suspension_events_no_update;
+DROP TRIGGER suspension_events_no_delete;
+
+-- Actors can always be pseudonymized to 0, reasons can be cleared once the suspension has ended
+CREATE TRIGGER suspension_events_no_update BEFORE UPDATE ON suspension_events
+WHEN NOT (
+    NEW.id = OLD.id
+    AND NEW.suspension_id = OLD.suspension_id
+    AND NEW.action = OLD.action
+    AND NEW.created_at = OLD.created_at
+    AND NEW.source = OLD.source
+    AND (NEW.actor_id IS OLD.actor_id OR NEW.actor_id = 0)
+    AND (NEW.reason IS OLD.reason OR (NEW.reason IS NULL AND NOT EXISTS (SELECT 1 FROM suspensions WHERE id = OLD.suspension_id AND active = TRUE)))
+)
+BEGIN
+    SELECT RAISE(ABORT, 'suspension_events is append-only');
+END;
+
+-- Events of ended suspensions are deleted together with the suspension
+CREATE TRIGGER suspension_events_no_delete BEFORE DELETE ON suspension_events
+WHEN EXISTS (SELECT 1 FROM suspensions WHERE id = OLD.suspension_id AND active = TRUE)
+BEGIN
+    SELECT RAISE(ABORT, 'suspension_events is append-only');
+END;
@@ -0,0 +1,21 @@
+-- Full-text index over the reasons of suspensions, the triggers keep it in sync
+CREATE VIRTUAL TABLE suspensions_fts USING fts5(reason, lift_reason, content = 'suspensions', content_rowid = 'id');
+
+INSERT INTO suspensions_fts (suspensions_fts) VALUES ('rebuild');
+
+CREATE TRIGGER suspensions_fts_insert AFTER INSERT ON suspensions
+BEGIN
+    INSERT INTO suspensions_fts (rowid, reason, lift_reason) VALUES (NEW.id, NEW.reason, NEW.lift_reason);
+END;
+
+-- Erased reasons have to leave the index as well
+CREATE TRIGGER suspensions_fts_delete AFTER DELETE ON suspensions
+BEGIN
+    INSERT INTO suspensions_fts (suspensions_fts, rowid, reason, lift_reason) VALUES ('delete', OLD.id, OLD.reason, OLD.lift_reason);
+END;
+
+CREATE TRIGGER suspensions_fts_update AFTER UPDATE OF reason, lift_reason ON suspensions
+BEGIN
+    INSERT INTO suspensions_fts (suspensions_fts, rowid, reason, lift_reason) VALUES ('delete', OLD.id, OLD.reason, OLD.lift_reason);
+    INSERT INTO suspensions_fts (rowid, reason, lift_reason) VALUES (NEW.id, NEW.reason, NEW.lift_reason);
+END;
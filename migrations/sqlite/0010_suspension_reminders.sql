@@ -0,0 +1,7 @@
+-- Reminders sent to staff before a suspension ends, so none is sent twice
+CREATE TABLE suspension_reminders (
+    suspension_id INTEGER NOT NULL REFERENCES suspensions(id) ON DELETE CASCADE,
+    seconds_before_expiry INTEGER NOT NULL,
+    sent_at TEXT NOT NULL,
+    PRIMARY KEY (suspension_id, seconds_before_expiry)
+);
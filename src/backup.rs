@@ -0,0 +1,215 @@
+use chrono::Utc;
+use sqlx::{Connection, Row, SqliteConnection};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::time::{interval_at, Duration, Instant};
+use tracing::{error, info};
+use crate::config::{BackupConfig, DatabaseConfig};
+use crate::db::{SqliteDatabase, SuspensionRepository};
+use crate::Error;
+
+const SNAPSHOT_PREFIX: &str = "database-";
+const SNAPSHOT_EXTENSION: &str = ".db";
+
+// Take a snapshot into the backup directory and delete the oldest ones beyond the configured count
+pub async fn take_snapshot(db: &dyn SuspensionRepository, backup_config: &BackupConfig) -> Result<PathBuf, Error> {
+
+    let directory = Path::new(&backup_config.directory);
+    fs::create_dir_all(directory)?;
+
+    let path = directory.join(format!("{}{}{}", SNAPSHOT_PREFIX, Utc::now().format("%Y%m%d-%H%M%S"), SNAPSHOT_EXTENSION));
+    db.snapshot(&path).await?;
+
+    rotate_snapshots(directory, backup_config.keep)?;
+
+    Ok(path)
+}
+
+// Take snapshots in the configured interval, the first one after one interval
+pub async fn schedule_snapshots(db: &dyn SuspensionRepository, backup_config: &BackupConfig) {
+
+    let Some(interval_in_hours) = backup_config.interval_in_hours else {
+        return;
+    };
+
+    let period = Duration::from_secs(interval_in_hours.max(1) * 60 * 60);
+    let mut interval = interval_at(Instant::now() + period, period);
+
+    loop {
+        interval.tick().await;
+
+        match take_snapshot(db, backup_config).await {
+            Ok(path) => info!(path = %path.display(), "Saved database snapshot"),
+            Err(error) => error!(%error, "Failed to save database snapshot"),
+        }
+    }
+}
+
+// Delete all but the newest snapshots, the timestamp in the name sorts them
+fn rotate_snapshots(directory: &Path, keep: usize) -> Result<(), Error> {
+
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_EXTENSION)))
+        .collect();
+
+    snapshots.sort();
+
+    for snapshot in snapshots.iter().rev().skip(keep.max(1)) {
+        fs::remove_file(snapshot)?;
+    }
+
+    Ok(())
+}
+
+// Make sure a snapshot is a healthy database of this bot
+pub async fn check_integrity(path: &Path) -> Result<(), Error> {
+
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let mut connection = SqliteConnection::connect_with(&options).await?;
+
+    let result: String = sqlx::query("PRAGMA integrity_check")
+        .fetch_one(&mut connection)
+        .await?
+        .get(0);
+
+    if result != "ok" {
+        return Err(format!("integrity check of {} failed: {}", path.display(), result).into());
+    }
+
+    sqlx::query("SELECT COUNT(*) FROM suspensions")
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|_| format!("{} is not a database of this bot", path.display()))?;
+
+    connection.close().await?;
+
+    Ok(())
+}
+
+// Replace the SQLite database with a snapshot, the bot must not be running
+// The current database is kept next to it with a .before-restore suffix
+pub async fn restore_snapshot(db_url: &str, db_config: &DatabaseConfig, snapshot: &Path) -> Result<(), Error> {
+
+    check_integrity(snapshot).await?;
+
+    let target = SqliteConnectOptions::from_str(db_url)?.get_filename().to_path_buf();
+
+    if target.exists() {
+
+        let previous = PathBuf::from(format!("{}.before-restore", target.display()));
+        if previous.exists() {
+            fs::remove_file(&previous)?;
+        }
+
+        let current = SqliteDatabase::connect(db_url, db_config).await.map_err(|error| error.to_string())?;
+        current.snapshot(&previous).await?;
+        current.pool.close().await;
+    }
+
+    // Copy next to the target first, so the swap itself can't leave a half-written database
+    let staged = PathBuf::from(format!("{}.restoring", target.display()));
+    fs::copy(snapshot, &staged)?;
+
+    // The write-ahead log belongs to the replaced database
+    for suffix in ["-wal", "-shm"] {
+        let path = PathBuf::from(format!("{}{}", target.display(), suffix));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    fs::rename(&staged, &target)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("lsp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // VACUUM INTO keeps in-memory databases in memory, so snapshots need a database file
+    async fn file_database(path: &Path) -> SqliteDatabase {
+        let db = SqliteDatabase::connect(&format!("sqlite://{}?mode=rwc", path.display()), &DatabaseConfig::default()).await.unwrap();
+        db.migrate().await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_rotated_and_restorable() {
+
+        let directory = temp_directory("snapshots");
+        let backup_config = BackupConfig { directory: directory.display().to_string(), interval_in_hours: None, keep: 2 };
+        let db = file_database(&directory.join("live.db")).await;
+
+        // Older snapshots and unrelated files
+        fs::write(directory.join("database-20000101-000000.db"), "").unwrap();
+        fs::write(directory.join("database-20000102-000000.db"), "").unwrap();
+        fs::write(directory.join("notes.txt"), "").unwrap();
+
+        let path = take_snapshot(&db, &backup_config).await.unwrap();
+        check_integrity(&path).await.unwrap();
+
+        let mut remaining: Vec<String> = fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        remaining.sort();
+
+        assert!(remaining.iter().all(|name| name != "database-20000101-000000.db"));
+        assert!(remaining.contains(&String::from("database-20000102-000000.db")));
+        assert!(remaining.contains(&String::from("notes.txt")));
+        assert_eq!(remaining.iter().filter(|name| name.starts_with(SNAPSHOT_PREFIX)).count(), 2);
+
+        // An empty file is not a usable snapshot
+        assert!(check_integrity(&directory.join("database-20000102-000000.db")).await.is_err());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[tokio::test]
+    async fn restore_keeps_the_previous_database() {
+
+        let directory = temp_directory("restore");
+        let db_url = format!("sqlite://{}?mode=rwc", directory.join("database.db").display());
+        let db_config = DatabaseConfig::default();
+
+        let snapshot = directory.join("snapshot.db");
+        let saved = file_database(&directory.join("saved.db")).await;
+        sqlx::query("INSERT INTO suspensions (guild_id, user_id, moderator_id, from_datetime, until_datetime, active) VALUES (1, 2, 3, '', '', TRUE)")
+            .execute(&saved.pool)
+            .await
+            .unwrap();
+        saved.snapshot(&snapshot).await.unwrap();
+
+        let current = SqliteDatabase::connect(&db_url, &db_config).await.unwrap();
+        current.migrate().await.unwrap();
+        current.pool.close().await;
+
+        restore_snapshot(&db_url, &db_config, &snapshot).await.unwrap();
+
+        assert!(directory.join("database.db.before-restore").is_file());
+        assert!(!directory.join("database.db.restoring").exists());
+        check_integrity(&directory.join("database.db")).await.unwrap();
+
+        let restored = SqliteDatabase::connect(&db_url, &db_config).await.unwrap();
+        assert_eq!(restored.count_active_suspensions().await.unwrap(), 1);
+        restored.pool.close().await;
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -1,17 +1,108 @@
+use chrono::Duration;
 use serde::Deserialize;
+use std::collections::HashSet;
 use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::helper::parse_duration;
+use crate::{Error, CONFIG};
+
+pub(crate) const CONFIG_PATH: &str = "config.toml";
 
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct Config {
     pub(crate) monitoring_interval_in_seconds: u64,
+    #[serde(default)]
+    pub(crate) database: DatabaseConfig,
+    #[serde(default)]
+    pub(crate) metrics: MetricsConfig,
     pub(crate) guilds: Vec<GuildConfig>,
 }
 
+// Address of the /healthz and /metrics server, needs the metrics feature, None turns it off
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub(crate) struct MetricsConfig {
+    pub(crate) listen: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct DatabaseConfig {
+    pub(crate) url: Option<String>,
+    pub(crate) max_connections: u32,
+    pub(crate) min_connections: u32,
+    pub(crate) acquire_timeout_in_seconds: u64,
+    pub(crate) wal: bool,
+    pub(crate) backup: BackupConfig,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout_in_seconds: 30,
+            wal: true,
+            backup: BackupConfig::default(),
+        }
+    }
+}
+
+// Snapshots of SQLite databases, None as interval only takes them on demand
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct BackupConfig {
+    pub(crate) directory: String,
+    pub(crate) interval_in_hours: Option<u64>,
+    pub(crate) keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            directory: String::from("backups"),
+            interval_in_hours: None,
+            keep: 7,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub(crate) struct GuildConfig {
     pub(crate) id: u64,
     pub(crate) channels: Channels,
     pub(crate) roles: Roles,
+    #[serde(default)]
+    pub(crate) retention: Option<Retention>,
+    #[serde(default)]
+    pub(crate) reconcile_on_startup: ReconcileMode,
+    // Unset leaves manual changes alone, so the server members intent is not needed
+    #[serde(default)]
+    pub(crate) manual_role_removal: Option<ManualRoleRemoval>,
+    // How long before a suspension ends staff is reminded, e.g. "24h"
+    #[serde(default)]
+    pub(crate) reminders: Vec<String>,
+}
+
+// What to do when someone removes the suspended role by hand during an active suspension
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ManualRoleRemoval {
+    // Lift the suspension in the name of whoever removed the role and restore the roles
+    Lift,
+    // Give the suspended role back
+    Reapply,
+}
+
+// What to do at startup when roles and active suspensions disagree
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReconcileMode {
+    Off,
+    #[default]
+    Report,
+    Fix,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -25,11 +116,117 @@ pub(crate) struct Channels {
 pub(crate) struct Roles {
     pub(crate) suspended: u64,
     pub(crate) suspend_permitted: Vec<u64>,
+    #[serde(default)]
+    pub(crate) max_suspension_durations: Vec<DurationLimit>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct DurationLimit {
+    pub(crate) role: u64,
+    pub(crate) max_duration: String,
+}
+
+// How long ended suspensions are kept and what happens to them afterwards
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Retention {
+    pub(crate) keep_for_days: u32,
+    pub(crate) mode: RetentionMode,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RetentionMode {
+    // Delete the suspensions and their events
+    Purge,
+    // Keep the suspensions for statistics, but without user and reasons
+    Anonymize,
 }
 
 impl Config {
+    // The config in use right now, a reload swaps it for commands, event handlers and the monitor alike
+    pub fn current() -> Arc<Config> {
+        CONFIG.read().unwrap().clone()
+    }
+
+    // Read and validate a config file
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<Config, Error> {
+        let config: Config = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Catch mistakes that would otherwise only show once a command or the monitor runs into them
+    fn validate(&self) -> Result<(), Error> {
+
+        if self.monitoring_interval_in_seconds == 0 {
+            return Err("monitoring_interval_in_seconds must be at least 1".into());
+        }
+
+        let mut guild_ids = HashSet::new();
+
+        for guild_config in &self.guilds {
+
+            if !guild_ids.insert(guild_config.id) {
+                return Err(format!("guild {} is configured more than once", guild_config.id).into());
+            }
+
+            let durations = guild_config.roles.max_suspension_durations.iter()
+                .map(|limit| &limit.max_duration)
+                .chain(&guild_config.reminders);
+
+            for duration in durations {
+                if parse_duration(duration).is_none() {
+                    return Err(format!("guild {}: \"{}\" is not a valid duration", guild_config.id, duration).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Member updates are a privileged intent, requested only when a guild reacts to them
+    pub fn needs_members_intent(&self) -> bool {
+        self.guilds.iter().any(|guild_config| guild_config.manual_role_removal.is_some())
+    }
+
     pub fn get_guild_config(&self, guild_id: u64) -> Option<&GuildConfig> {
         self.guilds.iter().find(|g| g.id == guild_id)
     }
 }
 
+impl GuildConfig {
+    // Members with one of the permitted roles and administrators may suspend
+    pub fn may_suspend(&self, role_ids: &[u64], is_administrator: bool) -> bool {
+        is_administrator || role_ids.iter().any(|role_id| self.roles.suspend_permitted.contains(role_id))
+    }
+
+    // Get the configured reminders, loading the config already rejects invalid durations
+    pub fn get_reminders(&self) -> Vec<Duration> {
+        self.reminders.iter().filter_map(|reminder| parse_duration(reminder)).collect()
+    }
+
+    // Get the longest suspension duration the given roles allow, None means unlimited
+    pub fn get_max_suspension_duration(&self, role_ids: &[u64]) -> Option<(Duration, &str)> {
+
+        let limits = &self.roles.max_suspension_durations;
+
+        // Permitted roles without a configured limit are unlimited
+        let has_unlimited_role = role_ids.iter()
+            .filter(|role_id| self.roles.suspend_permitted.contains(role_id))
+            .any(|role_id| !limits.iter().any(|limit| limit.role == *role_id));
+
+        if has_unlimited_role {
+            return None;
+        }
+
+        limits.iter()
+            .filter(|limit| role_ids.contains(&limit.role))
+            .filter_map(|limit| parse_duration(&limit.max_duration).map(|duration| (duration, limit.max_duration.as_str())))
+            .max_by_key(|(duration, _)| *duration)
+    }
+}
+
@@ -0,0 +1,126 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Duration;
+use tracing::{error, info, warn};
+use crate::config::{Config, CONFIG_PATH};
+use crate::{Error, CONFIG};
+
+// Editors often write a file in several steps, reload once it has been quiet for this long
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+// What changed with a reload
+pub struct Reload {
+    pub guilds: usize,
+    // The database, metrics server and gateway intents are set up once at startup
+    pub needs_restart: bool,
+}
+
+// Parse and validate config.toml and swap it in, an invalid file keeps the current config
+pub fn reload() -> Result<Reload, Error> {
+    swap(&CONFIG, Config::load(Path::new(CONFIG_PATH))?)
+}
+
+fn swap(current: &RwLock<Arc<Config>>, config: Config) -> Result<Reload, Error> {
+
+    let config = Arc::new(config);
+    let previous = std::mem::replace(&mut *current.write().map_err(|error| error.to_string())?, config.clone());
+
+    Ok(Reload {
+        guilds: config.guilds.len(),
+        needs_restart: previous.database != config.database
+            || previous.metrics != config.metrics
+            || previous.needs_members_intent() != config.needs_members_intent(),
+    })
+}
+
+// Reload config.toml whenever it changes on disk and wake the monitor for the new settings
+pub async fn watch(schedule_changed: Arc<Notify>) {
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+
+        // Reading the file is an event too, only writes and replacements count
+        let is_change = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+        if is_change && event.paths.iter().any(|path| path.file_name().is_some_and(|name| name == CONFIG_PATH)) {
+            let _ = sender.send(());
+        }
+    });
+
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            error!(%error, "Failed to watch config.toml, use /config reload instead");
+            return;
+        }
+    };
+
+    // Watch the directory, editors that save by replacing the file would end a watch on the file itself
+    if let Err(error) = watcher.watch(Path::new("."), RecursiveMode::NonRecursive) {
+        error!(%error, "Failed to watch config.toml, use /config reload instead");
+        return;
+    }
+
+    while receiver.recv().await.is_some() {
+
+        tokio::time::sleep(SETTLE_TIME).await;
+        while receiver.try_recv().is_ok() {}
+
+        match reload() {
+            Ok(reload) => {
+                info!(guilds = reload.guilds, "Reloaded config.toml");
+                if reload.needs_restart {
+                    warn!("Database, metrics or manual_role_removal settings changed, they only apply after a restart");
+                }
+                schedule_changed.notify_one();
+            }
+            Err(error) => error!(%error, "config.toml is invalid, keeping the current config"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ManualRoleRemoval;
+
+    const GUILD: &str = r#"
+        [[guilds]]
+        id = 1
+        channels = { ban_log = 2, ban_log_staff = 3, event_log = 4 }
+        roles = { suspended = 5, suspend_permitted = [6] }
+    "#;
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+
+        assert!(Config::parse(&format!("monitoring_interval_in_seconds = 60\n{}", GUILD)).is_ok());
+        assert!(Config::parse(&format!("monitoring_interval_in_seconds = 0\n{}", GUILD)).is_err());
+        assert!(Config::parse(&format!("monitoring_interval_in_seconds = 60\n{}{}", GUILD, GUILD)).is_err());
+        assert!(Config::parse(&format!("monitoring_interval_in_seconds = 60\n{}reminders = [\"tomorrow\"]", GUILD)).is_err());
+        assert!(Config::parse("monitoring_interval_in_seconds = \"60\"").is_err());
+    }
+
+    #[test]
+    fn reloads_report_settings_that_need_a_restart() {
+
+        let mut config = Config::parse(&format!("monitoring_interval_in_seconds = 60\n{}", GUILD)).unwrap();
+        let current = RwLock::new(Arc::new(config.clone()));
+
+        config.monitoring_interval_in_seconds = 30;
+        assert!(!swap(&current, config.clone()).unwrap().needs_restart);
+        assert_eq!(current.read().unwrap().monitoring_interval_in_seconds, 30);
+
+        config.database.max_connections += 1;
+        assert!(swap(&current, config.clone()).unwrap().needs_restart);
+
+        // Watching manual role removals needs another gateway intent
+        config.guilds[0].manual_role_removal = Some(ManualRoleRemoval::Reapply);
+        assert!(swap(&current, config).unwrap().needs_restart);
+    }
+}
@@ -0,0 +1,288 @@
+pub(crate) mod sqlite;
+#[cfg(feature = "postgres")]
+pub(crate) mod postgres;
+#[cfg(test)]
+mod tests;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::async_trait;
+use sqlx::migrate::{MigrateError, Migration};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::config::DatabaseConfig;
+
+pub use sqlite::SqliteDatabase;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresDatabase;
+
+// Stands in for users whose data was anonymized or erased
+pub const ERASED_USER_ID: i64 = 0;
+
+// Used when neither DATABASE_URL nor database.url in config.toml is set
+pub const DEFAULT_DATABASE_URL: &str = "sqlite://database.db?mode=rwc";
+
+// Connect to the database without touching the schema, the URL scheme picks the backend
+pub async fn connect(db_url: &str, db_config: &DatabaseConfig) -> Result<Arc<dyn SuspensionRepository>, Box<dyn Error>> {
+
+    if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+
+        #[cfg(feature = "postgres")]
+        return Ok(Arc::new(PostgresDatabase::connect(db_url, db_config).await?));
+
+        #[cfg(not(feature = "postgres"))]
+        return Err("PostgreSQL support requires building with the postgres feature".into());
+    }
+
+    Ok(Arc::new(SqliteDatabase::connect(db_url, db_config).await?))
+}
+
+// Everything the bot persists goes through this trait
+#[async_trait]
+pub trait SuspensionRepository: Send + Sync {
+
+    // Describe where the data lives, without credentials
+    fn location(&self) -> String;
+
+    // Whether snapshot can write a copy, other backends have their own backup tools
+    fn supports_snapshots(&self) -> bool;
+
+    // Write a consistent copy of the whole database to a new file
+    async fn snapshot(&self, path: &Path) -> Result<(), sqlx::Error>;
+
+    // Wait for running queries and close all connections
+    async fn close(&self);
+
+    // Apply all migrations that have not been applied yet
+    async fn migrate(&self) -> Result<(), MigrateError>;
+
+    // Get all migrations that have not been applied yet
+    async fn get_pending_migrations(&self) -> Result<Vec<&'static Migration>, MigrateError>;
+
+    // Log a suspension, the roles removed by it and its create event, returns the new suspension id
+    // Fails with a unique violation if the user already has an active suspension in the guild
+    async fn log_suspension(&self, suspension: Suspension, source: ActionSource) -> Result<i64, sqlx::Error>;
+
+    // Insert historical suspensions as they are, all or none, returns how many were inserted
+    async fn import_suspensions(&self, suspensions: &[Suspension]) -> Result<u64, sqlx::Error>;
+
+    // Retrieve all suspensions for a specific user
+    async fn get_suspensions(&self, guild_id: i64, user_id: i64) -> Result<Vec<Suspension>, sqlx::Error>;
+
+    // Retrieve a single suspension by its id
+    async fn get_suspension(&self, suspension_id: i64) -> Result<Option<Suspension>, sqlx::Error>;
+
+    // Retrieve all active suspensions for a specific user
+    async fn get_active_suspensions(&self, guild_id: i64, user_id: i64) -> Result<Vec<Suspension>, sqlx::Error>;
+
+    // Retrieve all suspensions of a guild matching the filter, oldest first
+    async fn find_suspensions(&self, filter: &SuspensionFilter) -> Result<Vec<Suspension>, sqlx::Error>;
+
+    // Full-text search over the reasons of a guild's suspensions, best matches first
+    async fn search_suspensions(&self, guild_id: i64, query: &str, limit: i64) -> Result<Vec<SearchHit>, sqlx::Error>;
+
+    // Count the active suspensions across all guilds
+    async fn count_active_suspensions(&self) -> Result<i64, sqlx::Error>;
+
+    // Count the active suspensions of each guild, guilds without any are left out
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    async fn count_active_suspensions_by_guild(&self) -> Result<Vec<(i64, i64)>, sqlx::Error>;
+
+    // Retrieve all active suspensions that ended at or before the given time and are not waiting for a retry
+    async fn get_expired_suspensions(&self, now: DateTime<Utc>) -> Result<Vec<Suspension>, sqlx::Error>;
+
+    // Get the earliest end of all active suspensions, None if nobody is suspended
+    async fn get_next_expiry(&self) -> Result<Option<DateTime<Utc>>, sqlx::Error>;
+
+    // Count a failed attempt to end a suspension and postpone the next one, returns the number of attempts so far
+    async fn record_expiry_failure(&self, suspension_id: i64, next_attempt_at: DateTime<Utc>) -> Result<i32, sqlx::Error>;
+
+    // Remember that a reminder was sent, returns false if it had been sent before
+    async fn record_reminder(&self, suspension_id: i64, seconds_before_expiry: i64, sent_at: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    // Move the end of an active suspension and record the edit event, returns false if it is no longer active
+    // Reminders that lie in the future again will be sent again
+    async fn extend_suspension(&self, event: SuspensionEvent, until: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+
+    // Retrieve all roles that were removed by a suspension
+    async fn get_removed_roles(&self, suspension_id: i64) -> Result<Vec<RemovedRole>, sqlx::Error>;
+
+    // Mark a suspension as no longer active and record why in the event log
+    async fn set_suspension_inactive(&self, event: SuspensionEvent) -> Result<(), sqlx::Error>;
+
+    // Retrieve the event log of a suspension, oldest first
+    async fn get_events(&self, suspension_id: i64) -> Result<Vec<SuspensionEvent>, sqlx::Error>;
+
+    // Delete ended suspensions of a guild that ended before the given time, returns how many were deleted
+    async fn purge_suspensions(&self, guild_id: i64, before: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // Remove user and reasons from ended suspensions of a guild that ended before the given time, returns how many were changed
+    async fn anonymize_suspensions(&self, guild_id: i64, before: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // Delete the ended suspensions of a user in a guild and pseudonymize the actions the user took as moderator
+    async fn forget_user(&self, guild_id: i64, user_id: i64) -> Result<ErasureReport, sqlx::Error>;
+}
+
+// Struct to map database rows to
+#[derive(Debug)]
+pub struct Suspension {
+    pub id: i64,
+    pub guild_id: i64,
+    pub user_id: i64,
+    pub moderator_id: i64,
+    pub removed_roles: Vec<RemovedRole>,
+    pub from_datetime: DateTime<Utc>,
+    pub until_datetime: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub active: Option<bool>,
+    pub lifted_by: Option<i64>,
+    pub lifted_at: Option<DateTime<Utc>>,
+    pub lift_reason: Option<String>,
+    pub expiry_attempts: i32,
+}
+
+// Narrows down find_suspensions, None matches everything
+#[derive(Debug, Default)]
+pub struct SuspensionFilter {
+    pub guild_id: i64,
+    pub user_id: Option<i64>,
+    pub moderator_id: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub active_only: bool,
+}
+
+// A suspension found by search_suspensions, the snippet marks the matches in bold
+#[derive(Debug)]
+pub struct SearchHit {
+    pub suspension: Suspension,
+    pub snippet: String,
+}
+
+// What forget_user removed or pseudonymized
+#[derive(Debug, PartialEq, Eq)]
+pub struct ErasureReport {
+    pub suspensions: u64,
+    pub events: u64,
+    pub pseudonymized: u64,
+}
+
+// A role removed by a suspension, named as it was at the time of removal
+#[derive(Debug, Clone)]
+pub struct RemovedRole {
+    pub role_id: i64,
+    pub role_name: Option<String>,
+}
+
+// An entry of the append-only event log
+#[derive(Debug, Clone)]
+pub struct SuspensionEvent {
+    pub suspension_id: i64,
+    pub action: SuspensionAction,
+    pub actor_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub source: ActionSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuspensionAction {
+    Create,
+    Lift,
+    Expire,
+    Edit,
+    // Ended right away because Discord did not apply it
+    Discard,
+}
+
+// Where an action came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionSource {
+    Command,
+    Monitor,
+    Appeal,
+    Import,
+    // Changes made by hand in Discord
+    Manual,
+    // Changes made while upgrading the database
+    Migration,
+    // Buttons on reminders before a suspension ends
+    Reminder,
+}
+
+impl SuspensionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuspensionAction::Create => "create",
+            SuspensionAction::Lift => "lift",
+            SuspensionAction::Expire => "expire",
+            SuspensionAction::Edit => "edit",
+            SuspensionAction::Discard => "discard",
+        }
+    }
+}
+
+impl FromStr for SuspensionAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "create" => Ok(SuspensionAction::Create),
+            "lift" => Ok(SuspensionAction::Lift),
+            "expire" => Ok(SuspensionAction::Expire),
+            "edit" => Ok(SuspensionAction::Edit),
+            "discard" => Ok(SuspensionAction::Discard),
+            _ => Err(format!("Unknown suspension action: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for SuspensionAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ActionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionSource::Command => "command",
+            ActionSource::Monitor => "monitor",
+            ActionSource::Appeal => "appeal",
+            ActionSource::Import => "import",
+            ActionSource::Manual => "manual",
+            ActionSource::Migration => "migration",
+            ActionSource::Reminder => "reminder",
+        }
+    }
+}
+
+impl FromStr for ActionSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "command" => Ok(ActionSource::Command),
+            "monitor" => Ok(ActionSource::Monitor),
+            "appeal" => Ok(ActionSource::Appeal),
+            "import" => Ok(ActionSource::Import),
+            "manual" => Ok(ActionSource::Manual),
+            "migration" => Ok(ActionSource::Migration),
+            "reminder" => Ok(ActionSource::Reminder),
+            _ => Err(format!("Unknown action source: {}", value)),
+        }
+    }
+}
+
+impl fmt::Display for ActionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Parse a text column into one of the enums above
+pub(crate) fn decode_enum<T: FromStr<Err = String>>(value: String) -> Result<T, sqlx::Error> {
+    value.parse().map_err(|error: String| sqlx::Error::Decode(error.into()))
+}
@@ -0,0 +1,637 @@
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::async_trait;
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool};
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow};
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+use crate::config::DatabaseConfig;
+use crate::db::{decode_enum, ActionSource, ErasureReport, SearchHit, ERASED_USER_ID, RemovedRole, Suspension, SuspensionAction, SuspensionEvent, SuspensionFilter, SuspensionRepository};
+
+// Migrations from ./migrations/sqlite, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+// When a suspension actually ended, lifts and discards end it before its scheduled end
+const ENDED_AT: &str = "MIN(until_datetime, COALESCE((SELECT MIN(created_at) FROM suspension_events WHERE suspension_id = suspensions.id AND action IN ('lift', 'expire', 'discard')), until_datetime))";
+
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    pub(crate) pool: SqlitePool,
+}
+
+impl SqliteDatabase {
+    // Connect to the database without touching the schema
+    pub async fn connect(db_url: &str, db_config: &DatabaseConfig) -> Result<Self, Box<dyn Error>> {
+
+        let mut options = SqliteConnectOptions::from_str(db_url)?;
+
+        // Starting in the wrong directory would otherwise silently give us an empty database
+        if !options.get_filename().exists() {
+            warn!(path = %options.get_filename().display(), "Database file does not exist yet, a new one will be created");
+        }
+
+        if db_config.wal {
+            options = options.journal_mode(SqliteJournalMode::Wal);
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(db_config.max_connections)
+            .min_connections(db_config.min_connections)
+            .acquire_timeout(Duration::from_secs(db_config.acquire_timeout_in_seconds))
+            .connect_with(options)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    // Create a migrated database that only lives in memory, used by tests
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, Box<dyn Error>> {
+
+        // Every connection to :memory: opens a new database, so keep exactly one alive
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+
+        let database = Self { pool };
+        database.migrate().await?;
+
+        Ok(database)
+    }
+
+    // Map suspension rows and load their removed roles
+    async fn to_suspensions(&self, rows: Vec<SqliteRow>) -> Result<Vec<Suspension>, sqlx::Error> {
+
+        let mut suspensions = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            suspensions.push(Suspension {
+                id: row.get("id"),
+                guild_id: row.get("guild_id"),
+                user_id: row.get("user_id"),
+                moderator_id: row.get("moderator_id"),
+                removed_roles: self.get_removed_roles(row.get("id")).await?,
+                from_datetime: row.get("from_datetime"),
+                until_datetime: row.get("until_datetime"),
+                reason: row.get("reason"),
+                active: row.get("active"),
+                lifted_by: row.get("lifted_by"),
+                lifted_at: row.get("lifted_at"),
+                lift_reason: row.get("lift_reason"),
+                expiry_attempts: row.get("expiry_attempts"),
+            });
+        }
+
+        Ok(suspensions)
+    }
+}
+
+#[async_trait]
+impl SuspensionRepository for SqliteDatabase {
+
+    fn location(&self) -> String {
+
+        let filename = self.pool.connect_options().get_filename().to_path_buf();
+
+        filename.canonicalize().unwrap_or(filename).display().to_string()
+    }
+
+    fn supports_snapshots(&self) -> bool {
+        true
+    }
+
+    async fn snapshot(&self, path: &Path) -> Result<(), sqlx::Error> {
+
+        // Unlike copying the file this includes the write-ahead log and works while the bot is writing
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn close(&self) {
+        self.pool.close().await
+    }
+
+    async fn migrate(&self) -> Result<(), MigrateError> {
+        MIGRATOR.run(&self.pool).await
+    }
+
+    async fn get_pending_migrations(&self) -> Result<Vec<&'static Migration>, MigrateError> {
+
+        let mut connection = self.pool.acquire().await?;
+        connection.ensure_migrations_table().await?;
+
+        let applied_migrations = connection.list_applied_migrations().await?;
+        let pending_migrations = MIGRATOR.iter()
+            .filter(|migration| migration.migration_type.is_up_migration())
+            .filter(|migration| !applied_migrations.iter().any(|applied| applied.version == migration.version))
+            .collect();
+
+        Ok(pending_migrations)
+    }
+
+    async fn log_suspension(&self, suspension: Suspension, source: ActionSource) -> Result<i64, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        let suspension_id = sqlx::query(
+            "INSERT INTO suspensions (guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+            .bind(suspension.guild_id)
+            .bind(suspension.user_id)
+            .bind(suspension.moderator_id)
+            .bind(suspension.from_datetime)
+            .bind(suspension.until_datetime)
+            .bind(&suspension.reason)
+            .bind(true)
+            .execute(&mut *transaction)
+            .await?
+            .last_insert_rowid();
+
+        for role in &suspension.removed_roles {
+            sqlx::query("INSERT OR IGNORE INTO suspension_roles (suspension_id, role_id, role_name) VALUES (?, ?, ?)")
+                .bind(suspension_id)
+                .bind(role.role_id)
+                .bind(&role.role_name)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        sqlx::query(
+            "INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+            .bind(suspension_id)
+            .bind(SuspensionAction::Create.as_str())
+            .bind(suspension.moderator_id)
+            .bind(suspension.from_datetime)
+            .bind(&suspension.reason)
+            .bind(source.as_str())
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(suspension_id)
+    }
+
+    async fn import_suspensions(&self, suspensions: &[Suspension]) -> Result<u64, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        for suspension in suspensions {
+            let suspension_id = sqlx::query(
+                "INSERT INTO suspensions (guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+                .bind(suspension.guild_id)
+                .bind(suspension.user_id)
+                .bind(suspension.moderator_id)
+                .bind(suspension.from_datetime)
+                .bind(suspension.until_datetime)
+                .bind(&suspension.reason)
+                .bind(suspension.active.unwrap_or(false))
+                .execute(&mut *transaction)
+                .await?
+                .last_insert_rowid();
+
+            for role in &suspension.removed_roles {
+                sqlx::query("INSERT OR IGNORE INTO suspension_roles (suspension_id, role_id, role_name) VALUES (?, ?, ?)")
+                    .bind(suspension_id)
+                    .bind(role.role_id)
+                    .bind(&role.role_name)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+
+            // How an imported suspension ended is unknown, only its creation is recorded
+            sqlx::query(
+                "INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+                .bind(suspension_id)
+                .bind(SuspensionAction::Create.as_str())
+                .bind(suspension.moderator_id)
+                .bind(suspension.from_datetime)
+                .bind(&suspension.reason)
+                .bind(ActionSource::Import.as_str())
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(suspensions.len() as u64)
+    }
+
+    async fn get_suspensions(&self, guild_id: i64, user_id: i64) -> Result<Vec<Suspension>, sqlx::Error> {
+
+        let rows = sqlx::query(
+            "SELECT id, guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active, lifted_by, lifted_at, lift_reason, expiry_attempts
+             FROM suspensions WHERE guild_id = ? AND user_id = ? ORDER BY id",
+        )
+            .bind(guild_id)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.to_suspensions(rows).await
+    }
+
+    async fn get_suspension(&self, suspension_id: i64) -> Result<Option<Suspension>, sqlx::Error> {
+
+        let rows = sqlx::query(
+            "SELECT id, guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active, lifted_by, lifted_at, lift_reason, expiry_attempts
+             FROM suspensions WHERE id = ?",
+        )
+            .bind(suspension_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(self.to_suspensions(rows).await?.pop())
+    }
+
+    async fn get_active_suspensions(&self, guild_id: i64, user_id: i64) -> Result<Vec<Suspension>, sqlx::Error> {
+
+        let rows = sqlx::query(
+            "SELECT id, guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active, lifted_by, lifted_at, lift_reason, expiry_attempts
+             FROM suspensions WHERE guild_id = ? AND user_id = ? AND active = TRUE ORDER BY id",
+        )
+            .bind(guild_id)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.to_suspensions(rows).await
+    }
+
+    async fn find_suspensions(&self, filter: &SuspensionFilter) -> Result<Vec<Suspension>, sqlx::Error> {
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active, lifted_by, lifted_at, lift_reason, expiry_attempts
+             FROM suspensions WHERE guild_id = ",
+        );
+        query.push_bind(filter.guild_id);
+
+        if let Some(user_id) = filter.user_id {
+            query.push(" AND user_id = ").push_bind(user_id);
+        }
+        if let Some(moderator_id) = filter.moderator_id {
+            query.push(" AND moderator_id = ").push_bind(moderator_id);
+        }
+        if let Some(from) = filter.from {
+            query.push(" AND from_datetime >= ").push_bind(from);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND from_datetime <= ").push_bind(until);
+        }
+        if filter.active_only {
+            query.push(" AND active = TRUE");
+        }
+
+        query.push(" ORDER BY id");
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+
+        self.to_suspensions(rows).await
+    }
+
+    async fn search_suspensions(&self, guild_id: i64, query: &str, limit: i64) -> Result<Vec<SearchHit>, sqlx::Error> {
+
+        // Quote every word, so user input can't be mistaken for FTS5 query syntax
+        let query = query.split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let rows = sqlx::query(
+            "SELECT suspensions.id, guild_id, user_id, moderator_id, from_datetime, until_datetime, suspensions.reason, active, lifted_by, lifted_at, suspensions.lift_reason, expiry_attempts,
+                    snippet(suspensions_fts, -1, '**', '**', '...', 16) AS snippet
+             FROM suspensions_fts JOIN suspensions ON suspensions.id = suspensions_fts.rowid
+             WHERE suspensions_fts MATCH ? AND guild_id = ?
+             ORDER BY rank LIMIT ?",
+        )
+            .bind(query)
+            .bind(guild_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let snippets: Vec<String> = rows.iter().map(|row| row.get("snippet")).collect();
+        let suspensions = self.to_suspensions(rows).await?;
+
+        Ok(suspensions.into_iter().zip(snippets).map(|(suspension, snippet)| SearchHit { suspension, snippet }).collect())
+    }
+
+    async fn count_active_suspensions(&self) -> Result<i64, sqlx::Error> {
+
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM suspensions WHERE active = TRUE")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("count"))
+    }
+
+    async fn count_active_suspensions_by_guild(&self) -> Result<Vec<(i64, i64)>, sqlx::Error> {
+
+        let rows = sqlx::query("SELECT guild_id, COUNT(*) AS count FROM suspensions WHERE active = TRUE GROUP BY guild_id ORDER BY guild_id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| (row.get("guild_id"), row.get("count"))).collect())
+    }
+
+    async fn get_expired_suspensions(&self, now: DateTime<Utc>) -> Result<Vec<Suspension>, sqlx::Error> {
+
+        let rows = sqlx::query(
+            "SELECT id, guild_id, user_id, moderator_id, from_datetime, until_datetime, reason, active, lifted_by, lifted_at, lift_reason, expiry_attempts
+             FROM suspensions WHERE until_datetime <= ? AND active = TRUE AND (next_attempt_at IS NULL OR next_attempt_at <= ?) ORDER BY id",
+        )
+            .bind(now)
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.to_suspensions(rows).await
+    }
+
+    async fn get_next_expiry(&self) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+
+        // Failed suspensions are due again at their next attempt
+        let row = sqlx::query("SELECT COALESCE(next_attempt_at, until_datetime) AS due FROM suspensions WHERE active = TRUE ORDER BY due LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("due")))
+    }
+
+    async fn record_expiry_failure(&self, suspension_id: i64, next_attempt_at: DateTime<Utc>) -> Result<i32, sqlx::Error> {
+
+        let row = sqlx::query("UPDATE suspensions SET expiry_attempts = expiry_attempts + 1, next_attempt_at = ? WHERE id = ? RETURNING expiry_attempts")
+            .bind(next_attempt_at)
+            .bind(suspension_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("expiry_attempts"))
+    }
+
+    async fn record_reminder(&self, suspension_id: i64, seconds_before_expiry: i64, sent_at: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+
+        let inserted = sqlx::query(
+            "INSERT OR IGNORE INTO suspension_reminders (suspension_id, seconds_before_expiry, sent_at) VALUES (?, ?, ?)",
+        )
+            .bind(suspension_id)
+            .bind(seconds_before_expiry)
+            .bind(sent_at)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        Ok(inserted > 0)
+    }
+
+    async fn extend_suspension(&self, event: SuspensionEvent, until: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        // A pending retry belongs to the old end
+        let updated = sqlx::query(
+            "UPDATE suspensions SET until_datetime = ?, expiry_attempts = 0, next_attempt_at = NULL
+             WHERE id = ? AND active = TRUE",
+        )
+            .bind(until)
+            .bind(event.suspension_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            transaction.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query(
+            "INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+            .bind(event.suspension_id)
+            .bind(event.action.as_str())
+            .bind(event.actor_id)
+            .bind(event.created_at)
+            .bind(&event.reason)
+            .bind(event.source.as_str())
+            .execute(&mut *transaction)
+            .await?;
+
+        sqlx::query("DELETE FROM suspension_reminders WHERE suspension_id = ? AND seconds_before_expiry < ?")
+            .bind(event.suspension_id)
+            .bind((until - event.created_at).num_seconds())
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn get_removed_roles(&self, suspension_id: i64) -> Result<Vec<RemovedRole>, sqlx::Error> {
+
+        let rows = sqlx::query("SELECT role_id, role_name FROM suspension_roles WHERE suspension_id = ?")
+            .bind(suspension_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let roles = rows
+            .into_iter()
+            .map(|row| RemovedRole {
+                role_id: row.get("role_id"),
+                role_name: row.get("role_name"),
+            })
+            .collect();
+
+        Ok(roles)
+    }
+
+    async fn set_suspension_inactive(&self, event: SuspensionEvent) -> Result<(), sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        // Early lifts are also kept on the suspension itself
+        let lifted = event.action == SuspensionAction::Lift;
+
+        let updated = sqlx::query(
+            "UPDATE suspensions SET active = FALSE, lifted_by = ?, lifted_at = ?, lift_reason = ?
+             WHERE id = ? AND active = TRUE",
+        )
+            .bind(event.actor_id.filter(|_| lifted))
+            .bind(Some(event.created_at).filter(|_| lifted))
+            .bind(event.reason.as_ref().filter(|_| lifted))
+            .bind(event.suspension_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        // Only the action that actually ended the suspension is recorded
+        if updated > 0 {
+            sqlx::query(
+                "INSERT INTO suspension_events (suspension_id, action, actor_id, created_at, reason, source)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+                .bind(event.suspension_id)
+                .bind(event.action.as_str())
+                .bind(event.actor_id)
+                .bind(event.created_at)
+                .bind(&event.reason)
+                .bind(event.source.as_str())
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await
+    }
+
+    async fn get_events(&self, suspension_id: i64) -> Result<Vec<SuspensionEvent>, sqlx::Error> {
+
+        let rows = sqlx::query(
+            "SELECT suspension_id, action, actor_id, created_at, reason, source
+             FROM suspension_events WHERE suspension_id = ? ORDER BY id",
+        )
+            .bind(suspension_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows
+            .into_iter()
+            .map(|row| Ok(SuspensionEvent {
+                suspension_id: row.get("suspension_id"),
+                action: decode_enum(row.get("action"))?,
+                actor_id: row.get("actor_id"),
+                created_at: row.get("created_at"),
+                reason: row.get("reason"),
+                source: decode_enum(row.get("source"))?,
+            }))
+            .collect()
+    }
+
+    async fn purge_suspensions(&self, guild_id: i64, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        // Picked before anything is deleted, the end of a suspension is read from its events
+        let suspension_ids: Vec<i64> = sqlx::query_scalar(&format!(
+            "SELECT id FROM suspensions WHERE guild_id = ? AND active = FALSE AND {} < ?", ENDED_AT,
+        ))
+            .bind(guild_id)
+            .bind(before)
+            .fetch_all(&mut *transaction)
+            .await?;
+
+        // Events first, they reference the suspension, removed roles are deleted along with it
+        for suspension_id in &suspension_ids {
+            for statement in [
+                "DELETE FROM suspension_events WHERE suspension_id = ?",
+                "DELETE FROM suspensions WHERE id = ?",
+            ] {
+                sqlx::query(statement)
+                    .bind(suspension_id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+        }
+
+        transaction.commit().await?;
+
+        Ok(suspension_ids.len() as u64)
+    }
+
+    async fn anonymize_suspensions(&self, guild_id: i64, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query(&format!(
+            "UPDATE suspension_events SET reason = NULL WHERE reason IS NOT NULL AND suspension_id IN
+             (SELECT id FROM suspensions WHERE guild_id = ? AND active = FALSE AND {} < ? AND user_id <> ?)", ENDED_AT,
+        ))
+            .bind(guild_id)
+            .bind(before)
+            .bind(ERASED_USER_ID)
+            .execute(&mut *transaction)
+            .await?;
+
+        let anonymized = sqlx::query(&format!(
+            "UPDATE suspensions SET user_id = ?, reason = NULL, lift_reason = NULL
+             WHERE guild_id = ? AND active = FALSE AND {} < ? AND user_id <> ?", ENDED_AT,
+        ))
+            .bind(ERASED_USER_ID)
+            .bind(guild_id)
+            .bind(before)
+            .bind(ERASED_USER_ID)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        transaction.commit().await?;
+
+        Ok(anonymized)
+    }
+
+    async fn forget_user(&self, guild_id: i64, user_id: i64) -> Result<ErasureReport, sqlx::Error> {
+
+        let mut transaction = self.pool.begin().await?;
+
+        // Suspensions of the user, active ones have to be lifted first
+        let events = sqlx::query(
+            "DELETE FROM suspension_events WHERE suspension_id IN
+             (SELECT id FROM suspensions WHERE guild_id = ? AND user_id = ? AND active = FALSE)",
+        )
+            .bind(guild_id)
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        let suspensions = sqlx::query("DELETE FROM suspensions WHERE guild_id = ? AND user_id = ? AND active = FALSE")
+            .bind(guild_id)
+            .bind(user_id)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected();
+
+        // Actions of the user as moderator stay, but no longer point to them
+        let mut pseudonymized = 0;
+
+        for statement in [
+            "UPDATE suspensions SET moderator_id = ? WHERE moderator_id = ? AND guild_id = ?",
+            "UPDATE suspensions SET lifted_by = ? WHERE lifted_by = ? AND guild_id = ?",
+            "UPDATE suspension_events SET actor_id = ? WHERE actor_id = ? AND suspension_id IN (SELECT id FROM suspensions WHERE guild_id = ?)",
+        ] {
+            pseudonymized += sqlx::query(statement)
+                .bind(ERASED_USER_ID)
+                .bind(user_id)
+                .bind(guild_id)
+                .execute(&mut *transaction)
+                .await?
+                .rows_affected();
+        }
+
+        transaction.commit().await?;
+
+        Ok(ErasureReport { suspensions, events, pseudonymized })
+    }
+}
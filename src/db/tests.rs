@@ -0,0 +1,429 @@
+use chrono::{Duration, SubsecRound, Utc};
+use crate::db::{ActionSource, ErasureReport, ERASED_USER_ID, RemovedRole, SqliteDatabase, Suspension, SuspensionAction, SuspensionEvent, SuspensionFilter, SuspensionRepository};
+
+// Every test runs against SQLite, and against PostgreSQL too if TEST_POSTGRES_URL is set
+async fn databases() -> Vec<Box<dyn SuspensionRepository>> {
+
+    #[cfg_attr(not(feature = "postgres"), allow(unused_mut))]
+    let mut databases: Vec<Box<dyn SuspensionRepository>> = vec![Box::new(SqliteDatabase::in_memory().await.unwrap())];
+
+    #[cfg(feature = "postgres")]
+    if let Ok(db_url) = std::env::var("TEST_POSTGRES_URL") {
+        databases.push(Box::new(crate::db::PostgresDatabase::isolated(&db_url).await.unwrap()));
+    }
+
+    databases
+}
+
+fn suspension(guild_id: i64, user_id: i64, length: Duration) -> Suspension {
+
+    let now = Utc::now().trunc_subsecs(0);
+
+    Suspension {
+        id: 0,
+        guild_id,
+        user_id,
+        moderator_id: 3,
+        removed_roles: vec![
+            RemovedRole { role_id: 10, role_name: Some(String::from("Member")) },
+            RemovedRole { role_id: 11, role_name: None },
+        ],
+        from_datetime: now,
+        until_datetime: now + length,
+        reason: Some(String::from("Spam")),
+        active: None,
+        lifted_by: None,
+        lifted_at: None,
+        lift_reason: None,
+        expiry_attempts: 0,
+    }
+}
+
+fn event(suspension_id: i64, action: SuspensionAction, actor_id: Option<i64>) -> SuspensionEvent {
+    SuspensionEvent {
+        suspension_id,
+        action,
+        actor_id,
+        created_at: Utc::now(),
+        reason: None,
+        source: if actor_id.is_some() { ActionSource::Command } else { ActionSource::Monitor },
+    }
+}
+
+#[tokio::test]
+async fn test_databases_are_migrated() {
+    for db in databases().await {
+        assert!(db.get_pending_migrations().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn suspension_lifecycle() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        // Freshly logged suspensions are active but not expired
+        let active_suspensions = db.get_active_suspensions(1, 2).await.unwrap();
+        assert_eq!(active_suspensions.len(), 1);
+        assert_eq!(active_suspensions[0].id, suspension_id);
+        assert_eq!(active_suspensions[0].reason.as_deref(), Some("Spam"));
+        assert_eq!(db.count_active_suspensions().await.unwrap(), 1);
+        assert_eq!(db.count_active_suspensions_by_guild().await.unwrap(), vec![(1, 1)]);
+        assert!(db.get_expired_suspensions(Utc::now()).await.unwrap().is_empty());
+
+        // Removed roles keep their names
+        let removed_roles = &active_suspensions[0].removed_roles;
+        assert_eq!(removed_roles.len(), 2);
+        assert!(removed_roles.iter().any(|role| role.role_id == 10 && role.role_name.as_deref() == Some("Member")));
+        assert!(removed_roles.iter().any(|role| role.role_id == 11 && role.role_name.is_none()));
+
+        // It expires once its end has passed
+        let expired_suspensions = db.get_expired_suspensions(Utc::now() + Duration::hours(2)).await.unwrap();
+        assert_eq!(expired_suspensions.len(), 1);
+        assert_eq!(expired_suspensions[0].id, suspension_id);
+
+        // Inactive suspensions stay in the history only
+        db.set_suspension_inactive(event(suspension_id, SuspensionAction::Expire, None)).await.unwrap();
+        assert!(db.get_active_suspensions(1, 2).await.unwrap().is_empty());
+        assert_eq!(db.count_active_suspensions().await.unwrap(), 0);
+        assert!(db.get_expired_suspensions(Utc::now() + Duration::hours(2)).await.unwrap().is_empty());
+
+        let suspensions = db.get_suspensions(1, 2).await.unwrap();
+        assert_eq!(suspensions.len(), 1);
+        assert_eq!(suspensions[0].active, Some(false));
+        assert_eq!(suspensions[0].lifted_by, None);
+    }
+}
+
+#[tokio::test]
+async fn discarded_suspensions_stay_in_the_event_log() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        let mut discard = event(suspension_id, SuspensionAction::Discard, Some(3));
+        discard.reason = Some(String::from("Discord did not apply it: Missing Permissions"));
+        db.set_suspension_inactive(discard).await.unwrap();
+
+        let suspensions = db.get_suspensions(1, 2).await.unwrap();
+        assert_eq!(suspensions.len(), 1);
+        assert_eq!(suspensions[0].active, Some(false));
+        assert_eq!(suspensions[0].lifted_by, None);
+        assert_eq!(db.count_active_suspensions().await.unwrap(), 0);
+
+        let actions: Vec<SuspensionAction> = db.get_events(suspension_id).await.unwrap().iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![SuspensionAction::Create, SuspensionAction::Discard]);
+
+        // The user can be suspended again right away
+        db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn users_have_at_most_one_active_suspension() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        let duplicate = db.log_suspension(suspension(1, 2, Duration::hours(2)), ActionSource::Command).await.unwrap_err();
+        assert!(duplicate.as_database_error().is_some_and(|error| error.is_unique_violation()));
+        assert_eq!(db.get_suspensions(1, 2).await.unwrap().len(), 1);
+
+        // Other guilds and ended suspensions don't count
+        db.log_suspension(suspension(4, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(suspension_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+        db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn reminders_are_recorded_once() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::days(7)), ActionSource::Command).await.unwrap();
+
+        assert!(db.record_reminder(suspension_id, 86400, Utc::now()).await.unwrap());
+        assert!(!db.record_reminder(suspension_id, 86400, Utc::now()).await.unwrap());
+        assert!(db.record_reminder(suspension_id, 3600, Utc::now()).await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn extending_resets_reminders_that_lie_ahead_again() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(12)), ActionSource::Command).await.unwrap();
+        db.record_reminder(suspension_id, 86400, Utc::now()).await.unwrap();
+        db.record_reminder(suspension_id, 3600, Utc::now()).await.unwrap();
+
+        let until = db.get_suspension(suspension_id).await.unwrap().unwrap().until_datetime + Duration::days(2);
+        assert!(db.extend_suspension(event(suspension_id, SuspensionAction::Edit, Some(7)), until).await.unwrap());
+
+        let extended = db.get_suspension(suspension_id).await.unwrap().unwrap();
+        assert_eq!(extended.until_datetime, until);
+        assert_eq!(db.get_events(suspension_id).await.unwrap().last().unwrap().action, SuspensionAction::Edit);
+
+        // Both reminders are ahead again
+        assert!(db.record_reminder(suspension_id, 86400, Utc::now()).await.unwrap());
+        assert!(db.record_reminder(suspension_id, 3600, Utc::now()).await.unwrap());
+
+        // Ended suspensions can't be extended
+        db.set_suspension_inactive(event(suspension_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+        assert!(!db.extend_suspension(event(suspension_id, SuspensionAction::Edit, Some(7)), until + Duration::days(1)).await.unwrap());
+        assert_eq!(db.get_suspension(suspension_id).await.unwrap().unwrap().until_datetime, until);
+        assert!(db.get_suspension(0).await.unwrap().is_none());
+    }
+}
+
+#[tokio::test]
+async fn suspensions_are_scoped_to_guild_and_user() {
+    for db in databases().await {
+        db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.log_suspension(suspension(1, 4, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.log_suspension(suspension(5, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        assert_eq!(db.get_suspensions(1, 2).await.unwrap().len(), 1);
+        assert_eq!(db.get_active_suspensions(5, 2).await.unwrap().len(), 1);
+        assert!(db.get_suspensions(5, 4).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn next_expiry_is_the_earliest_active_end() {
+    for db in databases().await {
+        assert_eq!(db.get_next_expiry().await.unwrap(), None);
+
+        let soon = suspension(1, 2, Duration::minutes(5));
+        let soon_until = soon.until_datetime;
+        let soon_id = db.log_suspension(soon, ActionSource::Command).await.unwrap();
+        let later = suspension(1, 4, Duration::hours(5));
+        let later_until = later.until_datetime;
+        db.log_suspension(later, ActionSource::Command).await.unwrap();
+
+        assert_eq!(db.get_next_expiry().await.unwrap(), Some(soon_until));
+
+        db.set_suspension_inactive(event(soon_id, SuspensionAction::Lift, Some(3))).await.unwrap();
+        assert_eq!(db.get_next_expiry().await.unwrap(), Some(later_until));
+    }
+}
+
+#[tokio::test]
+async fn failed_expiries_wait_for_their_retry() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::minutes(-1)), ActionSource::Command).await.unwrap();
+        let retry_at = Utc::now().trunc_subsecs(0) + Duration::minutes(1);
+
+        assert_eq!(db.record_expiry_failure(suspension_id, retry_at).await.unwrap(), 1);
+        assert_eq!(db.record_expiry_failure(suspension_id, retry_at).await.unwrap(), 2);
+
+        assert!(db.get_expired_suspensions(Utc::now()).await.unwrap().is_empty());
+        assert_eq!(db.get_next_expiry().await.unwrap(), Some(retry_at));
+
+        let due = db.get_expired_suspensions(retry_at).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].expiry_attempts, 2);
+    }
+}
+
+#[tokio::test]
+async fn find_suspensions_applies_every_filter() {
+    for db in databases().await {
+        let first_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.log_suspension(suspension(1, 4, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.log_suspension(suspension(5, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(first_id, SuspensionAction::Expire, None)).await.unwrap();
+
+        let find = |filter: SuspensionFilter| {
+            let db = &db;
+            async move { db.find_suspensions(&filter).await.unwrap().len() }
+        };
+
+        assert_eq!(find(SuspensionFilter { guild_id: 1, ..Default::default() }).await, 2);
+        assert_eq!(find(SuspensionFilter { guild_id: 1, user_id: Some(2), ..Default::default() }).await, 1);
+        assert_eq!(find(SuspensionFilter { guild_id: 1, moderator_id: Some(9), ..Default::default() }).await, 0);
+        assert_eq!(find(SuspensionFilter { guild_id: 1, active_only: true, ..Default::default() }).await, 1);
+        assert_eq!(find(SuspensionFilter { guild_id: 1, from: Some(Utc::now() + Duration::hours(1)), ..Default::default() }).await, 0);
+        assert_eq!(find(SuspensionFilter { guild_id: 1, until: Some(Utc::now() + Duration::hours(1)), ..Default::default() }).await, 2);
+    }
+}
+
+#[tokio::test]
+async fn imported_suspensions_stay_inactive() {
+    for db in databases().await {
+        let mut imported = suspension(1, 2, Duration::days(-1));
+        imported.active = Some(false);
+
+        assert_eq!(db.import_suspensions(&[imported]).await.unwrap(), 1);
+
+        let suspensions = db.get_suspensions(1, 2).await.unwrap();
+        assert_eq!(suspensions.len(), 1);
+        assert_eq!(suspensions[0].active, Some(false));
+        assert_eq!(suspensions[0].removed_roles.len(), 2);
+        assert!(db.get_expired_suspensions(Utc::now()).await.unwrap().is_empty());
+
+        let events = db.get_events(suspensions[0].id).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].source, ActionSource::Import);
+    }
+}
+
+#[tokio::test]
+async fn events_record_who_ended_a_suspension() {
+    for db in databases().await {
+        let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        // Lifting twice, e.g. by a command racing the monitor, only records the first
+        db.set_suspension_inactive(event(suspension_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+        db.set_suspension_inactive(event(suspension_id, SuspensionAction::Expire, None)).await.unwrap();
+
+        let events = db.get_events(suspension_id).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].action, SuspensionAction::Create);
+        assert_eq!(events[0].actor_id, Some(3));
+        assert_eq!(events[0].reason.as_deref(), Some("Spam"));
+        assert_eq!(events[1].action, SuspensionAction::Lift);
+        assert_eq!(events[1].actor_id, Some(7));
+        assert_eq!(events[1].source, ActionSource::Command);
+
+        // The lift is also kept on the suspension
+        let suspensions = db.get_suspensions(1, 2).await.unwrap();
+        assert_eq!(suspensions[0].lifted_by, Some(7));
+        assert!(suspensions[0].lifted_at.is_some());
+    }
+}
+
+#[tokio::test]
+async fn events_are_append_only() {
+
+    let db = SqliteDatabase::in_memory().await.unwrap();
+    db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+    assert!(sqlx::query("UPDATE suspension_events SET reason = NULL").execute(&db.pool).await.is_err());
+    assert!(sqlx::query("DELETE FROM suspension_events").execute(&db.pool).await.is_err());
+}
+
+#[tokio::test]
+async fn ended_events_can_only_be_redacted() {
+
+    let db = SqliteDatabase::in_memory().await.unwrap();
+    let suspension_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+    db.set_suspension_inactive(event(suspension_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+
+    assert!(sqlx::query("UPDATE suspension_events SET action = 'expire'").execute(&db.pool).await.is_err());
+    assert!(sqlx::query("UPDATE suspension_events SET actor_id = 8").execute(&db.pool).await.is_err());
+    assert!(sqlx::query("UPDATE suspension_events SET reason = NULL, actor_id = 0").execute(&db.pool).await.is_ok());
+    assert!(sqlx::query("DELETE FROM suspension_events").execute(&db.pool).await.is_ok());
+}
+
+#[tokio::test]
+async fn retention_only_touches_ended_suspensions() {
+    for db in databases().await {
+        let ended_id = db.log_suspension(suspension(1, 2, Duration::days(-2)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(ended_id, SuspensionAction::Expire, None)).await.unwrap();
+        let other_guild_id = db.log_suspension(suspension(5, 2, Duration::days(-2)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(other_guild_id, SuspensionAction::Expire, None)).await.unwrap();
+        // Not picked up by the monitor yet
+        db.log_suspension(suspension(1, 4, Duration::days(-2)), ActionSource::Command).await.unwrap();
+
+        let before = Utc::now() - Duration::days(1);
+
+        assert_eq!(db.anonymize_suspensions(1, before).await.unwrap(), 1);
+        assert_eq!(db.anonymize_suspensions(1, before).await.unwrap(), 0);
+        assert!(db.get_suspensions(1, 2).await.unwrap().is_empty());
+
+        let anonymized = db.get_suspensions(1, ERASED_USER_ID).await.unwrap();
+        assert_eq!(anonymized.len(), 1);
+        assert_eq!(anonymized[0].reason, None);
+        assert!(db.get_events(ended_id).await.unwrap().iter().all(|event| event.reason.is_none()));
+
+        assert_eq!(db.purge_suspensions(1, before).await.unwrap(), 1);
+        assert!(db.get_suspensions(1, ERASED_USER_ID).await.unwrap().is_empty());
+        assert!(db.get_events(ended_id).await.unwrap().is_empty());
+
+        assert_eq!(db.get_suspensions(1, 4).await.unwrap().len(), 1);
+        assert_eq!(db.get_suspensions(5, 2).await.unwrap().len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn retention_counts_from_when_suspensions_really_ended() {
+    for db in databases().await {
+        // Year long suspensions, one lifted early and one that Discord did not apply
+        let lifted_id = db.log_suspension(suspension(1, 2, Duration::days(365)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(lifted_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+        let discarded_id = db.log_suspension(suspension(1, 4, Duration::days(365)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(discarded_id, SuspensionAction::Discard, Some(3))).await.unwrap();
+
+        assert_eq!(db.anonymize_suspensions(1, Utc::now() - Duration::days(1)).await.unwrap(), 0);
+        assert_eq!(db.anonymize_suspensions(1, Utc::now() + Duration::seconds(1)).await.unwrap(), 2);
+
+        assert_eq!(db.purge_suspensions(1, Utc::now() + Duration::seconds(1)).await.unwrap(), 2);
+        assert!(db.get_suspensions(1, ERASED_USER_ID).await.unwrap().is_empty());
+        assert!(db.get_events(lifted_id).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn forget_user_erases_and_pseudonymizes() {
+    for db in databases().await {
+        let lifted_id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+        db.set_suspension_inactive(event(lifted_id, SuspensionAction::Lift, Some(7))).await.unwrap();
+        db.log_suspension(suspension(1, 7, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        // The active suspension of 7 stays, their lift is pseudonymized on the suspension and in the event log
+        assert_eq!(db.forget_user(1, 7).await.unwrap(), ErasureReport { suspensions: 0, events: 0, pseudonymized: 2 });
+        assert_eq!(db.get_active_suspensions(1, 7).await.unwrap().len(), 1);
+        assert_eq!(db.get_suspensions(1, 2).await.unwrap()[0].lifted_by, Some(ERASED_USER_ID));
+
+        // Moderator 3 issued both suspensions
+        assert_eq!(db.forget_user(1, 3).await.unwrap(), ErasureReport { suspensions: 0, events: 0, pseudonymized: 4 });
+
+        assert_eq!(db.forget_user(1, 2).await.unwrap(), ErasureReport { suspensions: 1, events: 2, pseudonymized: 0 });
+        assert!(db.get_suspensions(1, 2).await.unwrap().is_empty());
+        assert!(db.get_events(lifted_id).await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn forgotten_moderators_leave_their_active_suspensions_running() {
+    for db in databases().await {
+        let id = db.log_suspension(suspension(1, 2, Duration::hours(1)), ActionSource::Command).await.unwrap();
+
+        db.forget_user(1, 3).await.unwrap();
+
+        // The suspension still ends on time and can be read back with the erased moderator
+        let active = db.get_active_suspensions(1, 2).await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].moderator_id, ERASED_USER_ID);
+        assert_eq!(db.get_suspension(id).await.unwrap().unwrap().moderator_id, ERASED_USER_ID);
+        assert_eq!(db.get_expired_suspensions(Utc::now() + Duration::hours(2)).await.unwrap()[0].id, id);
+        assert_eq!(db.get_events(id).await.unwrap()[0].actor_id, Some(ERASED_USER_ID));
+    }
+}
+
+#[tokio::test]
+async fn search_finds_reasons_of_the_guild() {
+    for db in databases().await {
+        let mut scam = suspension(1, 2, Duration::hours(1));
+        scam.reason = Some(String::from("Posted a crypto scam link in general"));
+        let scam_id = db.log_suspension(scam, ActionSource::Command).await.unwrap();
+
+        let mut other_guild = suspension(5, 2, Duration::hours(1));
+        other_guild.reason = Some(String::from("Crypto scam as well"));
+        db.log_suspension(other_guild, ActionSource::Command).await.unwrap();
+
+        let hits = db.search_suspensions(1, "crypto SCAM", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].suspension.id, scam_id);
+        assert!(hits[0].snippet.contains("**crypto**"));
+
+        // Query syntax is taken literally
+        assert!(db.search_suspensions(1, "crypto\" OR \"", 10).await.unwrap().is_empty());
+        assert!(db.search_suspensions(1, "   ", 10).await.unwrap().is_empty());
+
+        // Lift reasons are searchable, erased reasons are not
+        let mut lift = event(scam_id, SuspensionAction::Lift, Some(7));
+        lift.reason = Some(String::from("Appeal accepted"));
+        db.set_suspension_inactive(lift).await.unwrap();
+        assert_eq!(db.search_suspensions(1, "appeal", 10).await.unwrap().len(), 1);
+
+        db.forget_user(1, 2).await.unwrap();
+        assert!(db.search_suspensions(1, "crypto", 10).await.unwrap().is_empty());
+    }
+}
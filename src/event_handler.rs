@@ -1,50 +1,69 @@
-use std::future::Future;
-use poise::serenity_prelude::{Context, ChannelId, CreateEmbed, EventHandler, GuildId, audit_log, MessageId, Message, MessageUpdateEvent, CreateEmbedAuthor, CreateEmbedFooter, User, Member, AuditLogEntry, CreateMessage};
-use crate::CONFIG;
-
-pub struct Handler;
+use chrono::Utc;
+use poise::serenity_prelude::{Context, ChannelId, CreateEmbed, EventHandler, GuildId, audit_log, MessageId, Message, MessageUpdateEvent, CreateEmbedAuthor, CreateEmbedFooter, User, Member, AuditLogEntry, CreateMessage, GuildMemberUpdateEvent, Interaction, RoleId, UserId, Colour, Mentionable};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::error;
+use crate::config::{Config, ManualRoleRemoval};
+use crate::db::{ActionSource, SuspensionAction, SuspensionEvent, SuspensionRepository};
+use crate::{helper, metrics, reminders};
+use crate::shutdown::Shutdown;
+
+// How long Discord may take to show a role change in the audit log
+const AUDIT_LOG_ATTEMPTS: u32 = 3;
+const AUDIT_LOG_MAX_AGE_IN_SECONDS: i64 = 30;
+
+pub struct Handler {
+    pub database: Arc<dyn SuspensionRepository>,
+    pub schedule_changed: Arc<Notify>,
+    pub shutdown: Arc<Shutdown>,
+}
 
 #[poise::serenity_prelude::async_trait]
 impl EventHandler for Handler {
 
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+
+        // Slash commands are handled by poise, only the reminder buttons are left
+        if let Err(error) = reminders::handle_interaction(&ctx, &interaction, self.database.as_ref(), &self.schedule_changed, &self.shutdown).await {
+            error!(%error, "Failed to handle reminder interaction");
+        }
+    }
+
     async fn message_delete(&self, ctx: Context, channel_id: ChannelId, deleted_message_id: MessageId, guild_id: Option<GuildId>) -> () {
 
         if guild_id.is_none() {
             return;
         }
 
-        let config = CONFIG.read().unwrap();
+        let config = Config::current();
         let guild_id = guild_id.unwrap();
         let guild_config = config.get_guild_config(guild_id.get());
 
         if let Some(guild_config) = guild_config {
 
             let event_log_channel_id = ChannelId::new(guild_config.channels.event_log);
-            let deleted_message = ctx.cache.message(channel_id, deleted_message_id).expect("Deleted message not found in cache");
+            let deleted_message = ctx.cache.message(channel_id, deleted_message_id).expect("Deleted message not found in cache").clone();
             let mut user_who_deleted = None;
 
             // Get the guild's audit logs
             if let Ok(audit_logs) = guild_id.audit_logs(&ctx, Option::from(audit_log::Action::Message(audit_log::MessageAction::Delete)), None, None, None).await {
                 if let Some(log_entry) = audit_logs.entries.first() {
-                    user_who_deleted = ctx.cache.user(log_entry.user_id);
+                    user_who_deleted = ctx.cache.user(log_entry.user_id).map(|user| user.clone());
                 }
             }
 
             let embed = CreateEmbed::default()
                 .title("Message Delete")
-                .author(CreateEmbedAuthor::new(&deleted_message.author.name).icon_url(&deleted_message.author.avatar_url().unwrap_or_default()))
+                .author(CreateEmbedAuthor::new(&deleted_message.author.name).icon_url(deleted_message.author.avatar_url().unwrap_or_default()))
                 .field("Content", &deleted_message.content, false)
-                .field("Deleted by", if user_who_deleted.is_some() {
-                    &user_who_deleted.unwrap().name
-                } else {
-                    "Unknown"
-                }, false)
+                .field("Deleted by", user_who_deleted.map(|user| user.name).unwrap_or_else(|| String::from("Unknown")), false)
                 .footer(CreateEmbedFooter::new(format!("<t:{}:f>", &deleted_message.timestamp.timestamp().to_string())));
 
-            event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await.unwrap();
+            let sent = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await;
+            metrics::event_log_message(&sent);
+            sent.unwrap();
         }
-
-        Ok(())
     }
 
     async fn message_update(&self, ctx: Context, old_if_available: Option<Message>, new: Option<Message>, event: MessageUpdateEvent) {
@@ -53,7 +72,7 @@ impl EventHandler for Handler {
             return;
         }
 
-        let config = CONFIG.read().unwrap();
+        let config = Config::current();
         let guild_id = event.guild_id.unwrap();
         let guild_config = config.get_guild_config(guild_id.get());
 
@@ -69,13 +88,14 @@ impl EventHandler for Handler {
                 .field("New", new.unwrap().content, false)
                 .footer(CreateEmbedFooter::new(format!("<t:{}:f>",  &event.timestamp.unwrap().timestamp().to_string())));
 
-            let _ = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed));
+            let sent = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await;
+            metrics::event_log_message(&sent);
         }
     }
 
     async fn guild_member_removal(&self, ctx: Context, guild_id: GuildId, user: User, member_data_if_available: Option<Member>) {
 
-        let config = CONFIG.read().unwrap();
+        let config = Config::current();
         let guild_config = config.get_guild_config(guild_id.get());
 
         if let Some(guild_config) = guild_config {
@@ -87,20 +107,132 @@ impl EventHandler for Handler {
                 .author(CreateEmbedAuthor::new(&user.name).icon_url(user.avatar_url().unwrap_or_default()))
                 .footer(CreateEmbedFooter::new(format!("Joined at <t:{}:f>",  &member_data_if_available.unwrap().joined_at.unwrap().to_string())));
 
-            let _ = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed));
+            let sent = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await;
+            metrics::event_log_message(&sent);
+        }
+    }
+
+    async fn guild_member_update(&self, ctx: Context, _old_if_available: Option<Member>, _new: Option<Member>, event: GuildMemberUpdateEvent) {
+
+        let config = Config::current();
+        let Some(guild_config) = config.get_guild_config(event.guild_id.get()) else {
+            return;
+        };
+
+        let Some(manual_role_removal) = guild_config.manual_role_removal else {
+            return;
+        };
+
+        // Only a missing suspended role is of interest
+        let suspended_role = RoleId::new(guild_config.roles.suspended);
+        if event.roles.contains(&suspended_role) {
+            return;
+        }
+
+        let Some(_in_flight) = self.shutdown.begin() else {
+            return;
+        };
+
+        let active_suspensions = match self.database.get_active_suspensions(event.guild_id.get() as i64, event.user.id.get() as i64).await {
+            Ok(active_suspensions) => active_suspensions,
+            Err(error) => {
+                error!(guild_id = %event.guild_id, user_id = %event.user.id, %error, "Failed to get active suspensions");
+                return;
+            }
+        };
+
+        let Some(suspension) = active_suspensions.first() else {
+            return;
+        };
+
+        let staff_log_channel_id = ChannelId::new(guild_config.channels.ban_log_staff);
+
+        // Without knowing who removed the role, it might have been the bot itself, so only report it
+        let Some(actor) = get_role_update_actor(&ctx, event.guild_id, event.user.id).await else {
+
+            // While suspending, the role is missing for a moment
+            if event.guild_id.member(&ctx, event.user.id).await.is_ok_and(|member| member.roles.contains(&suspended_role)) {
+                return;
+            }
+
+            let message = format!("### Suspended role removed\r\n{} lost the suspended role during an active suspension, but the audit log doesn't say by whom. Nothing was changed.", event.user.mention());
+            let _ = staff_log_channel_id.send_message(&ctx.http, CreateMessage::default().content(message)).await;
+
+            return;
+        };
+
+        // The bot removes the role itself when suspending and when a suspension ends
+        if actor == ctx.cache.current_user().id {
+            return;
         }
+
+        let actor_mention = actor.mention().to_string();
+
+        let embed = match manual_role_removal {
+            ManualRoleRemoval::Lift => {
+
+                let lifted = self.database.set_suspension_inactive(SuspensionEvent {
+                    suspension_id: suspension.id,
+                    action: SuspensionAction::Lift,
+                    actor_id: Some(actor.get() as i64),
+                    created_at: Utc::now(),
+                    reason: Some(String::from("Suspended role removed by hand")),
+                    source: ActionSource::Manual,
+                }).await;
+
+                if let Err(error) = lifted {
+                    error!(suspension_id = suspension.id, guild_id = suspension.guild_id, user_id = suspension.user_id, %error, "Failed to lift suspension after manual role removal");
+                    return;
+                }
+
+                self.schedule_changed.notify_one();
+
+                let restored = helper::restore_roles(&ctx.http, event.guild_id, suspended_role.get(), suspension).await;
+
+                CreateEmbed::default()
+                    .title("Suspension Lifted")
+                    .author(CreateEmbedAuthor::new(&event.user.name).icon_url(event.user.avatar_url().unwrap_or_default()))
+                    .color(Colour::DARK_GREEN)
+                    .field("User", event.user.mention().to_string(), false)
+                    .field("Lifted by", actor_mention, false)
+                    .field("Was until", helper::datetime_to_discord_timestamp(&suspension.until_datetime), false)
+                    .field("Reason", "Suspended role removed by hand", true)
+                    .field("Roles restored", match restored {
+                        Ok(_) => String::from("Yes"),
+                        Err(error) => format!("No: {}", error),
+                    }, true)
+            }
+            ManualRoleRemoval::Reapply => {
+
+                let reapplied = ctx.http.add_member_role(event.guild_id, event.user.id, suspended_role, Some("Suspension is still active")).await;
+
+                CreateEmbed::default()
+                    .title("Suspended Role Reapplied")
+                    .author(CreateEmbedAuthor::new(&event.user.name).icon_url(event.user.avatar_url().unwrap_or_default()))
+                    .color(Colour::DARK_RED)
+                    .field("User", event.user.mention().to_string(), false)
+                    .field("Removed by", actor_mention, false)
+                    .field("Until", helper::datetime_to_discord_timestamp(&suspension.until_datetime), false)
+                    .field("Reapplied", match reapplied {
+                        Ok(()) => String::from("Yes"),
+                        Err(error) => format!("No: {}", error),
+                    }, true)
+            }
+        };
+
+        let _ = staff_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await;
     }
 
     async fn guild_audit_log_entry_create(&self, ctx: Context, entry: AuditLogEntry, guild_id: GuildId) {
 
-        let config = CONFIG.read().unwrap();
+        let config = Config::current();
         let guild_config = config.get_guild_config(guild_id.get());
-        let user = ctx.cache.user(entry.user_id).unwrap();
+        let user = ctx.cache.user(entry.user_id).unwrap().clone();
 
         let embed = match entry.action {
             audit_log::Action::Member(audit_log::MemberAction::Kick) => {
 
-                let kicked_user = ctx.cache.user(entry.target_id.unwrap().get()).unwrap();
+                let kicked_user = ctx.cache.user(entry.target_id.unwrap().get()).unwrap().clone();
 
                 Some (
                     CreateEmbed::default()
@@ -117,8 +249,34 @@ impl EventHandler for Handler {
             let event_log_channel_id = ChannelId::new(guild_config.channels.event_log);
 
             if let Some(embed) = embed {
-                let _ = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed));
+                let sent = event_log_channel_id.send_message(&ctx.http, CreateMessage::default().embed(embed)).await;
+                metrics::event_log_message(&sent);
+            }
+        }
+    }
+}
+
+// Find who just changed the roles of a member, None if the audit log doesn't say
+async fn get_role_update_actor(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Option<UserId> {
+
+    for attempt in 1..=AUDIT_LOG_ATTEMPTS {
+
+        if let Ok(audit_logs) = guild_id.audit_logs(&ctx, Some(audit_log::Action::Member(audit_log::MemberAction::RoleUpdate)), None, None, Some(10)).await {
+
+            let entry = audit_logs.entries.iter().find(|entry| {
+                entry.target_id.is_some_and(|target_id| target_id.get() == user_id.get())
+                    && Utc::now().timestamp() - entry.id.created_at().unix_timestamp() <= AUDIT_LOG_MAX_AGE_IN_SECONDS
+            });
+
+            if let Some(entry) = entry {
+                return Some(entry.user_id);
             }
         }
+
+        if attempt < AUDIT_LOG_ATTEMPTS {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
     }
-}
\ No newline at end of file
+
+    None
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::db::Suspension;
+use crate::Error;
+
+// One suspension as it appears in exported files
+// Ids are strings because spreadsheets and JSON parsers round numbers this large
+#[derive(Debug, Serialize)]
+pub struct ExportRow {
+    pub id: i64,
+    pub guild_id: String,
+    pub user_id: String,
+    pub moderator_id: String,
+    pub from_datetime: String,
+    pub from_readable: String,
+    pub until_datetime: String,
+    pub until_readable: String,
+    pub reason: Option<String>,
+    pub active: bool,
+    pub removed_roles: String,
+    pub lifted_by: Option<String>,
+    pub lifted_at: Option<String>,
+    pub lifted_at_readable: Option<String>,
+    pub lift_reason: Option<String>,
+}
+
+impl From<&Suspension> for ExportRow {
+    fn from(suspension: &Suspension) -> Self {
+        Self {
+            id: suspension.id,
+            guild_id: suspension.guild_id.to_string(),
+            user_id: suspension.user_id.to_string(),
+            moderator_id: suspension.moderator_id.to_string(),
+            from_datetime: suspension.from_datetime.to_rfc3339(),
+            from_readable: readable(&suspension.from_datetime),
+            until_datetime: suspension.until_datetime.to_rfc3339(),
+            until_readable: readable(&suspension.until_datetime),
+            reason: suspension.reason.clone(),
+            active: suspension.active.unwrap_or(false),
+            removed_roles: suspension.removed_roles.iter()
+                .map(|role| match &role.role_name {
+                    Some(role_name) => format!("{} ({})", role_name, role.role_id),
+                    None => role.role_id.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+            lifted_by: suspension.lifted_by.map(|lifted_by| lifted_by.to_string()),
+            lifted_at: suspension.lifted_at.map(|lifted_at| lifted_at.to_rfc3339()),
+            lifted_at_readable: suspension.lifted_at.as_ref().map(readable),
+            lift_reason: suspension.lift_reason.clone(),
+        }
+    }
+}
+
+fn readable(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+pub fn to_csv(suspensions: &[Suspension]) -> Result<Vec<u8>, Error> {
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for suspension in suspensions {
+        writer.serialize(ExportRow::from(suspension))?;
+    }
+
+    Ok(writer.into_inner().map_err(|error| error.into_error())?)
+}
+
+pub fn to_json(suspensions: &[Suspension]) -> Result<Vec<u8>, Error> {
+
+    let rows: Vec<ExportRow> = suspensions.iter().map(ExportRow::from).collect();
+
+    Ok(serde_json::to_vec_pretty(&rows)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+    use crate::db::RemovedRole;
+
+    fn suspension() -> Suspension {
+        Suspension {
+            id: 1,
+            guild_id: 1339214142892150834,
+            user_id: 2,
+            moderator_id: 3,
+            removed_roles: vec![
+                RemovedRole { role_id: 10, role_name: Some(String::from("Member")) },
+                RemovedRole { role_id: 11, role_name: None },
+            ],
+            from_datetime: Utc.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap(),
+            until_datetime: Utc.with_ymd_and_hms(2025, 1, 2, 10, 0, 0).unwrap(),
+            reason: Some(String::from("Spam, again")),
+            active: Some(false),
+            lifted_by: Some(4),
+            lifted_at: Some(Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap()),
+            lift_reason: None,
+            expiry_attempts: 0,
+        }
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_line_per_suspension() {
+
+        let csv = String::from_utf8(to_csv(&[suspension()]).unwrap()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id,guild_id,user_id,moderator_id,from_datetime,from_readable"));
+        assert!(lines[1].contains("1339214142892150834"));
+        assert!(lines[1].contains("2025-01-01 10:00:00 UTC"));
+        assert!(lines[1].contains("\"Spam, again\""));
+        assert!(lines[1].contains("Member (10); 11"));
+    }
+
+    #[test]
+    fn json_keeps_ids_exact() {
+
+        let json: serde_json::Value = serde_json::from_slice(&to_json(&[suspension()]).unwrap()).unwrap();
+
+        assert_eq!(json[0]["guild_id"], "1339214142892150834");
+        assert_eq!(json[0]["lifted_by"], "4");
+        assert_eq!(json[0]["lift_reason"], serde_json::Value::Null);
+        assert_eq!(json[0]["until_readable"], "2025-01-02 10:00:00 UTC");
+    }
+}
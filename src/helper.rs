@@ -1,25 +1,68 @@
-use std::borrow::Cow;
-use chrono::{Local, NaiveDateTime};
-use poise::serenity_prelude::{GuildId, Http, Member, RoleId, User};
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::{EditMember, GuildId, Http, HttpError, Member, Mentionable, RoleId, User};
+use regex::Regex;
 use crate::config::Config;
 use crate::{Context, Error};
 use crate::db::Suspension;
 
-pub fn date_string_to_discord_timestamp(date_string: &str) -> String {
-    let datetime = NaiveDateTime::parse_from_str(date_string, "%Y-%m-%d %H:%M:%S%.9f").expect("Failed to parse datetime");
-    format!("<t:{}>", datetime.and_local_timezone(Local).unwrap().timestamp())
+pub fn datetime_to_discord_timestamp(datetime: &DateTime<Utc>) -> String {
+    format!("<t:{}>", datetime.timestamp())
 }
 
-pub async fn member_has_suspension_permission(ctx: &Context<'_>, member: &Cow<'_, Member>) -> bool {
+pub fn parse_duration(duration: &str) -> Option<Duration> {
 
-    let config = &ctx.data().config;
+    let re = Regex::new(r"^(\d+)([shdwm])$").unwrap();
+    let caps = re.captures(duration)?;
+    let value: i64 = caps[1].parse().ok()?; // Extract number
+    let unit = &caps[2]; // Extract time unit ('s', 'h', 'd', 'w' or 'm')
+
+    match unit {
+        "s" => Duration::try_seconds(value),
+        "h" => Duration::try_hours(value),
+        "d" => Duration::try_days(value),
+        "w" => Duration::try_weeks(value),
+        "m" => Duration::try_days(value.checked_mul(30)?), // Approximation of a month (30 days)
+        _ => None
+    }
+}
+
+pub async fn duration_within_limit(ctx: &Context<'_>, member: &Member, duration: Duration) -> bool {
+
+    // Administrators are never limited
+    if member.permissions.is_some_and(|permissions| permissions.administrator()) {
+        return true;
+    }
+
+    let config = &Config::current();
+    let guild_id = ctx.guild_id().unwrap().get();
+    let guild_config = Config::get_guild_config(config, guild_id).unwrap();
+    let role_ids: Vec<u64> = member.roles.iter().map(|role_id| role_id.get()).collect();
+
+    if let Some((max_duration, max_duration_string)) = guild_config.get_max_suspension_duration(&role_ids) {
+        if duration > max_duration {
+
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!(":x: You can only suspend for up to **{}**!", max_duration_string))
+                    .ephemeral(true)
+            ).await.expect("Failed to send duration-limit-reply");
+
+            return false;
+        }
+    }
+
+    true
+}
+
+pub async fn member_has_suspension_permission(ctx: &Context<'_>, member: &Member) -> bool {
+
+    let config = &Config::current();
     let guild_id = &ctx.guild_id().unwrap().get();
-    let guild_config = Config::get_guild_config(&config, *guild_id).unwrap();
-    let permitted_roles = &guild_config.roles.suspend_permitted;
+    let guild_config = Config::get_guild_config(config, *guild_id).unwrap();
+    let role_ids: Vec<u64> = member.roles.iter().map(|role_id| role_id.get()).collect();
     
-    let members_permitted_roles = member.roles.iter().filter(|role_id| permitted_roles.contains(&role_id.get())).collect::<Vec<_>>();
-    
-    if members_permitted_roles.is_empty() && !member.permissions.unwrap().administrator() {
+    if !guild_config.may_suspend(&role_ids, member.permissions.is_some_and(|permissions| permissions.administrator())) {
 
         ctx.send(
             poise::CreateReply::default()
@@ -33,34 +76,164 @@ pub async fn member_has_suspension_permission(ctx: &Context<'_>, member: &Cow<'_
     true
 }
 
+pub async fn member_is_administrator(ctx: &Context<'_>, member: &Member) -> bool {
+
+    if !member.permissions.is_some_and(|permissions| permissions.administrator()) {
+
+        ctx.send(
+            poise::CreateReply::default()
+                .content(":x: Only administrators can do that!")
+                .ephemeral(true)
+        ).await.expect("Failed to send not-administrator-reply");
+
+        return false;
+    }
+
+    true
+}
+
+// Tells the author who suspended the user already, if anyone did
 pub async fn user_is_suspended(ctx: &Context<'_>, user: &User) -> bool {
     
     let guild_id = ctx.guild_id().unwrap().get();
     let db = &ctx.data().database;
     let active_suspensions = db.get_active_suspensions(guild_id as i64, user.id.get() as i64).await.unwrap();
-    
-    active_suspensions.len() > 0
+
+    if let Some(suspension) = active_suspensions.first() {
+
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!(":x: {} is already suspended by <@{}> until {}!", user.mention(), suspension.moderator_id, datetime_to_discord_timestamp(&suspension.until_datetime)))
+                .ephemeral(true)
+        ).await.expect("Failed to send already-suspended-reply");
+
+        return true;
+    }
+
+    false
+}
+
+// The database allows only one active suspension per user and guild
+pub fn is_duplicate_suspension(error: &sqlx::Error) -> bool {
+    error.as_database_error().is_some_and(|error| error.is_unique_violation())
 }
 
-pub async fn restore_roles(http: &Http, guild: GuildId, suspended_role_id: u64, suspension: &Suspension) -> Result<(), Error> {
+// Swap the suspended role for the removed roles in a single request, so Discord applies all of it or nothing
+// Returns the roles the member had before, to undo the change if needed
+pub async fn restore_roles(http: &Http, guild: GuildId, suspended_role_id: u64, suspension: &Suspension) -> Result<Vec<RoleId>, Error> {
 
-    let guild_member = guild.member(&http, suspension.user_id as u64).await.unwrap();
+    let mut guild_member = guild.member(&http, suspension.user_id as u64).await?;
     let suspended_role = RoleId::from(suspended_role_id);
-    let role_ids = suspension.previous_roles.clone();
-    let role_ids_serenity: Vec<RoleId> = role_ids.iter()
-        .filter_map(|id| id.parse::<u64>().ok())
-        .map(RoleId::from)
+    let previous_roles = guild_member.roles.clone();
+    let guild_roles = guild.roles(&http).await?;
+
+    let mut roles: Vec<RoleId> = previous_roles.iter()
+        .filter(|role_id| **role_id != suspended_role)
+        .copied()
         .collect();
 
-    guild_member.remove_role(&http, suspended_role).await?;
+    for role in &suspension.removed_roles {
+        let role_id = RoleId::new(role.role_id as u64);
 
-    for role_id in role_ids_serenity {
-        // Check if the role still exists
-        if guild.role(&http, role_id).await.is_ok() {
-            // Give the role back to the member
-            guild_member.add_role(&http, &role_id).await?;
+        // Roles deleted in the meantime can't be given back
+        if guild_roles.contains_key(&role_id) && !roles.contains(&role_id) {
+            roles.push(role_id);
         }
     }
 
+    guild_member.edit(&http, EditMember::new().roles(roles)).await?;
+
+    Ok(previous_roles)
+}
+
+// Commands are refused while the bot shuts down
+pub async fn reply_restarting(ctx: &Context<'_>) -> Result<(), Error> {
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(":zzz: The bot is restarting, please try again in a moment!")
+            .ephemeral(true)
+    ).await?;
+
     Ok(())
 }
+
+// Discord answers with this code when a user is not on the server
+const UNKNOWN_MEMBER: isize = 10007;
+
+pub fn is_unknown_member(error: &Error) -> bool {
+    matches!(
+        error.downcast_ref::<serenity::Error>(),
+        Some(serenity::Error::Http(HttpError::UnsuccessfulRequest(response))) if response.error.code == UNKNOWN_MEMBER
+    )
+}
+
+// Discord rejects messages over 2000 characters, the rest is room for a closing line
+pub const MAX_MESSAGE_LENGTH: usize = 1900;
+
+// Cut text to at most max_length bytes without splitting a character
+pub fn truncate(text: &str, max_length: usize) -> String {
+
+    if text.len() <= max_length {
+        return text.to_string();
+    }
+
+    let mut end = max_length.saturating_sub(3);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}...", &text[..end])
+}
+
+// Fill up to max_messages messages with whole blocks of text, the blocks that don't fit are counted at the end
+pub fn paginate(header: String, blocks: Vec<String>, max_messages: usize, unit: &str) -> Vec<String> {
+
+    let mut messages = vec![header];
+    let mut remaining = blocks.len();
+
+    for block in blocks {
+        let block = truncate(&block, MAX_MESSAGE_LENGTH);
+        let message = messages.last_mut().unwrap();
+
+        if message.len() + block.len() <= MAX_MESSAGE_LENGTH {
+            message.push_str(&block);
+        } else if messages.len() < max_messages {
+            messages.push(block.trim_start().to_string());
+        } else {
+            break;
+        }
+
+        remaining -= 1;
+    }
+
+    if remaining > 0 {
+        messages.last_mut().unwrap().push_str(&format!("\r\n...and {} more {}", remaining, unit));
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_stay_within_the_limit() {
+
+        let blocks: Vec<String> = (0..30).map(|_| format!("\n{}", "x".repeat(400))).collect();
+
+        let messages = paginate(String::from("## Header"), blocks.clone(), 1, "block(s)");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].ends_with("...and 26 more block(s)"));
+
+        let messages = paginate(String::from("## Header"), blocks, 10, "block(s)");
+        assert_eq!(messages.len(), 8);
+        assert!(messages.iter().all(|message| message.len() <= 2000));
+        assert!(!messages[1].starts_with('\n'));
+
+        // A single block that is too long on its own is cut
+        let messages = paginate(String::new(), vec!["ä".repeat(2000)], 1, "block(s)");
+        assert!(messages[0].len() <= MAX_MESSAGE_LENGTH && messages[0].ends_with("..."));
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use crate::db::Suspension;
+use crate::Error;
+
+// One suspension as read from an imported file, the columns match our own export
+// Unknown columns are ignored, so exports of other bots only need renamed headers
+#[derive(Debug, Deserialize)]
+pub struct ImportRow {
+    pub user_id: Id,
+    pub moderator_id: Id,
+    pub from_datetime: String,
+    pub until_datetime: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+// Other bots write ids as numbers or as strings
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(u64),
+    Text(String),
+}
+
+impl Id {
+    fn parse(&self) -> Option<i64> {
+        let id = match self {
+            Id::Number(id) => *id,
+            Id::Text(id) => id.trim().parse().ok()?,
+        };
+
+        // Discord ids always fit into an i64, 0 is the placeholder of erased users in our own exports
+        i64::try_from(id).ok()
+    }
+}
+
+pub fn from_csv(data: &[u8]) -> Result<Vec<ImportRow>, Error> {
+
+    let mut reader = csv::Reader::from_reader(data);
+    let mut rows = Vec::new();
+
+    for row in reader.deserialize() {
+        rows.push(row?);
+    }
+
+    Ok(rows)
+}
+
+pub fn from_json(data: &[u8]) -> Result<Vec<ImportRow>, Error> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+// Turn a row into an inactive suspension of the guild, or explain what is wrong with it
+pub fn to_suspension(row: &ImportRow, guild_id: i64) -> Result<Suspension, String> {
+
+    let user_id = row.user_id.parse().ok_or("invalid user_id")?;
+    let moderator_id = row.moderator_id.parse().ok_or("invalid moderator_id")?;
+    let from_datetime = parse_datetime(&row.from_datetime).ok_or("invalid from_datetime")?;
+    let until_datetime = parse_datetime(&row.until_datetime).ok_or("invalid until_datetime")?;
+
+    if until_datetime < from_datetime {
+        return Err(String::from("until_datetime is before from_datetime"));
+    }
+
+    Ok(Suspension {
+        id: 0,
+        guild_id,
+        user_id,
+        moderator_id,
+        removed_roles: vec![],
+        from_datetime,
+        until_datetime,
+        reason: row.reason.clone().filter(|reason| !reason.trim().is_empty()),
+        active: Some(false),
+        lifted_by: None,
+        lifted_at: None,
+        lift_reason: None,
+        expiry_attempts: 0,
+    })
+}
+
+// Accepts RFC 3339 and the readable format of our export, times without offset are UTC
+fn parse_datetime(datetime: &str) -> Option<DateTime<Utc>> {
+
+    let datetime = datetime.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(datetime) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+
+    let datetime = datetime.strip_suffix(" UTC").unwrap_or(datetime);
+
+    NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S").ok().map(|datetime| datetime.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use crate::db::ERASED_USER_ID;
+    use super::*;
+
+    #[test]
+    fn csv_export_can_be_imported_again() {
+
+        let csv = "id,guild_id,user_id,moderator_id,from_datetime,until_datetime,reason,active\n\
+                   1,5,1339214142892150834,3,2025-01-01T10:00:00+00:00,2025-01-02 10:00:00 UTC,,true\n";
+        let rows = from_csv(csv.as_bytes()).unwrap();
+        let suspension = to_suspension(&rows[0], 7).unwrap();
+
+        assert_eq!(suspension.guild_id, 7);
+        assert_eq!(suspension.user_id, 1339214142892150834);
+        assert_eq!(suspension.until_datetime, Utc.with_ymd_and_hms(2025, 1, 2, 10, 0, 0).unwrap());
+        assert_eq!(suspension.reason, None);
+        assert_eq!(suspension.active, Some(false));
+    }
+
+    #[test]
+    fn anonymized_exports_can_be_imported_again() {
+
+        let from_datetime = Utc.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap();
+        let anonymized = Suspension {
+            id: 1,
+            guild_id: 5,
+            user_id: ERASED_USER_ID,
+            moderator_id: ERASED_USER_ID,
+            removed_roles: vec![],
+            from_datetime,
+            until_datetime: from_datetime + chrono::Duration::days(1),
+            reason: None,
+            active: Some(false),
+            lifted_by: Some(ERASED_USER_ID),
+            lifted_at: Some(from_datetime),
+            lift_reason: None,
+            expiry_attempts: 0,
+        };
+
+        let rows = from_csv(&crate::export::to_csv(&[anonymized]).unwrap()).unwrap();
+        let suspension = to_suspension(&rows[0], 7).unwrap();
+
+        assert_eq!(suspension.user_id, ERASED_USER_ID);
+        assert_eq!(suspension.moderator_id, ERASED_USER_ID);
+        assert_eq!(suspension.from_datetime, from_datetime);
+    }
+
+    #[test]
+    fn invalid_rows_are_explained() {
+
+        let json = r#"[
+            {"user_id": 2, "moderator_id": "3", "from_datetime": "2025-01-01 10:00:00", "until_datetime": "2025-01-02 10:00:00", "reason": "Spam"},
+            {"user_id": "someone", "moderator_id": 3, "from_datetime": "2025-01-01 10:00:00", "until_datetime": "2025-01-02 10:00:00"},
+            {"user_id": 2, "moderator_id": 3, "from_datetime": "yesterday", "until_datetime": "2025-01-02 10:00:00"},
+            {"user_id": 2, "moderator_id": 3, "from_datetime": "2025-01-02 10:00:00", "until_datetime": "2025-01-01 10:00:00"}
+        ]"#;
+        let rows = from_json(json.as_bytes()).unwrap();
+
+        assert_eq!(to_suspension(&rows[0], 7).unwrap().reason.as_deref(), Some("Spam"));
+        assert_eq!(to_suspension(&rows[1], 7).unwrap_err(), "invalid user_id");
+        assert_eq!(to_suspension(&rows[2], 7).unwrap_err(), "invalid from_datetime");
+        assert_eq!(to_suspension(&rows[3], 7).unwrap_err(), "until_datetime is before from_datetime");
+    }
+}
@@ -2,48 +2,115 @@ mod slash_commands;
 mod db;
 mod helper;
 mod config;
+mod config_reload;
 pub(crate) mod start_monitoring;
 mod event_handler;
+mod export;
+mod import;
+mod backup;
+mod reconcile;
+mod reminders;
+mod metrics;
+mod shutdown;
 
 use poise::serenity_prelude as serenity;
 use dotenv::dotenv;
-use crate::db::Database;
-use crate::config::Config;
+use crate::db::SuspensionRepository;
+use crate::config::{Config, CONFIG_PATH};
 use start_monitoring::start_monitoring;
 use event_handler::Handler;
+use shutdown::Shutdown;
 use once_cell::sync::Lazy;
-use toml;
-use std::fs;
-use std::sync::RwLock;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
 struct Data {
-    pub config: Config,
-    pub database: Database
+    pub database: Arc<dyn SuspensionRepository>,
+    // Wakes the monitoring task when a suspension is added or ended
+    pub schedule_changed: Arc<Notify>,
+    pub shutdown: Arc<Shutdown>,
 }
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
-pub static CONFIG: Lazy<RwLock<Config>> = Lazy::new(|| {
-    let content = fs::read_to_string("config.toml").unwrap();
-    toml::from_str(&content).unwrap()
+// Swapped as a whole on reload, so readers always see one consistent config
+pub(crate) static CONFIG: Lazy<RwLock<Arc<Config>>> = Lazy::new(|| {
+    let config = Config::load(Path::new(CONFIG_PATH)).unwrap_or_else(|error| panic!("Failed to load {}: {}", CONFIG_PATH, error));
+    RwLock::new(Arc::new(config))
 });
 
+// Log level from RUST_LOG, e.g. "debug" or "info,serenity=warn", LOG_FORMAT=json for log pipelines
+fn init_logging() {
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    if std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json")) {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
+
 #[tokio::main]
 async fn main() {
     
     // Load the environment variables from the .env file
     dotenv().ok();
 
-    // Load the config
-    let config = CONFIG.read().unwrap();
+    init_logging();
+
+    // Load the config, settings that are read once at startup need a restart to change
+    let config = Config::current();
+
+    // Connect to the database, DATABASE_URL in .env takes precedence over config.toml
+    let db_url = std::env::var("DATABASE_URL").ok()
+        .or_else(|| config.database.url.clone())
+        .unwrap_or_else(|| String::from(db::DEFAULT_DATABASE_URL));
+
+    // Replace the database with a snapshot and exit, the bot must not be running
+    if let Some(snapshot) = std::env::args().skip_while(|arg| arg != "--restore").nth(1) {
+        backup::restore_snapshot(&db_url, &config.database, Path::new(&snapshot)).await.expect("Failed to restore snapshot");
+        info!(snapshot, "Restored database");
+
+        return;
+    }
 
-    // Get the database
-    let database = Database::new().await.expect("Failed to initialize database");
+    let database = db::connect(&db_url, &config.database).await.expect("Failed to connect to database");
+
+    // List pending migrations without applying them
+    if std::env::args().any(|arg| arg == "--pending-migrations") {
+        let pending_migrations = database.get_pending_migrations().await.expect("Failed to read migrations");
+
+        println!("{} pending migration(s)", pending_migrations.len());
+        for migration in pending_migrations {
+            println!("{} {}", migration.version, migration.description);
+        }
+
+        return;
+    }
+
+    // Bring the database schema up to date
+    database.migrate().await.expect("Failed to migrate database");
+
+    let active_suspensions = database.count_active_suspensions().await.expect("Failed to count active suspensions");
+    info!(database = database.location(), active_suspensions, "Using database");
+
+    let schedule_changed = Arc::new(Notify::new());
+    let shutdown = Arc::new(Shutdown::default());
 
     // Configure the bot
     let token = std::env::var("DISCORD_TOKEN").expect("No DISCORD_TOKEN in .env");
-    let intents = serenity::GatewayIntents::non_privileged();
+    // Member updates are needed to notice a suspended role removed by hand
+    // The intent is privileged, bots without it enabled in the developer portal can't connect with it
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    if config.needs_members_intent() {
+        intents |= serenity::GatewayIntents::GUILD_MEMBERS;
+    }
 
     // Build the framework
     let framework = poise::Framework::builder()
@@ -52,18 +119,62 @@ async fn main() {
                 slash_commands::suspend::suspend(),
                 slash_commands::remove_suspension::remove_suspension(),
                 slash_commands::suspension_history::suspension_history(),
+                slash_commands::export::export(),
+                slash_commands::import::import(),
+                slash_commands::forget::forget(),
+                slash_commands::snapshot::snapshot(),
+                slash_commands::search::search(),
+                slash_commands::reconcile::reconcile(),
+                slash_commands::config::config(),
             ],
+            pre_command: |ctx| Box::pin(async move {
+                metrics::command_run(&ctx.command().qualified_name);
+            }),
+            on_error: |error| Box::pin(async move {
+                if let poise::FrameworkError::Command { error, .. } = &error {
+                    if error.downcast_ref::<serenity::Error>().is_some() {
+                        metrics::discord_error("command");
+                    }
+                }
+
+                // Snapshots and reloads affect every guild, so guild administrators are not enough
+                if let poise::FrameworkError::NotAnOwner { ctx, .. } = &error {
+                    let reply = poise::CreateReply::default()
+                        .content(":x: Only the owner of the bot can do that!")
+                        .ephemeral(true);
+
+                    if let Err(error) = ctx.send(reply).await {
+                        error!(%error, "Failed to handle error");
+                    }
+
+                    return;
+                }
+
+                if let Err(error) = poise::builtins::on_error(error).await {
+                    error!(%error, "Failed to handle error");
+                }
+            }),
+            // Refuse new commands while shutting down
+            command_check: Some(|ctx| Box::pin(async move {
+                if ctx.data().shutdown.is_requested() {
+                    helper::reply_restarting(&ctx).await?;
+                    return Ok(false);
+                }
+
+                Ok(true)
+            })),
             ..Default::default()
         })
         .setup({
-            // Clone the config and database because we need them later
-            let config = config.clone();
+            // Clone the database because we need it later
             let database = database.clone();
+            let schedule_changed = schedule_changed.clone();
+            let shutdown = shutdown.clone();
             
             move |ctx, _ready, framework| {
                 Box::pin(async move {
                     poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                    Ok(Data { config, database })
+                    Ok(Data { database, schedule_changed, shutdown })
                 })
             }
         })
@@ -72,25 +183,76 @@ async fn main() {
     // Build the client
     let mut client = serenity::ClientBuilder::new(token, intents)
         .framework(framework)
-        .event_handler(Handler)
+        .event_handler(Handler { database: database.clone(), schedule_changed: schedule_changed.clone(), shutdown: shutdown.clone() })
         .await
         .unwrap();
     
     // Print active guilds
     let guilds = client.http.get_guilds(None, None).await.unwrap();
-    println!("Connected to {} guild(s):\r\n{}", 
-             guilds.len(), 
-             guilds.iter()
-                 .map(|guild| guild.name.clone())
-                 .collect::<Vec<_>>()
-                 .join(", "));
+    info!(count = guilds.len(), guilds = guilds.iter()
+        .map(|guild| guild.name.clone())
+        .collect::<Vec<_>>()
+        .join(", "), "Connected to guilds");
     
+    // Spawn backup task, scheduled snapshots would fail every time on databases without snapshots
+    if database.supports_snapshots() {
+        let backup_database = database.clone();
+        let backup_config = config.database.backup.clone();
+        tokio::spawn( async move {
+            backup::schedule_snapshots(backup_database.as_ref(), &backup_config).await;
+        });
+    } else if config.database.backup.interval_in_hours.is_some() {
+        warn!(database = database.location(), "database.backup.interval_in_hours is set, but this database does not support snapshots");
+    }
+
+    // Spawn metrics and health endpoint
+    if let Some(listen) = config.metrics.listen.clone() {
+
+        #[cfg(feature = "metrics")]
+        {
+            let metrics_database = database.clone();
+            let shard_manager = client.shard_manager.clone();
+            let monitoring_interval = config.monitoring_interval_in_seconds;
+            tokio::spawn( async move {
+                if let Err(error) = metrics::serve(&listen, metrics_database, shard_manager, monitoring_interval).await {
+                    error!(listen, %error, "Failed to serve metrics");
+                }
+            });
+        }
+
+        #[cfg(not(feature = "metrics"))]
+        warn!(listen, "metrics.listen is set, but the bot was built without the metrics feature");
+    }
+
+    // Spawn config watcher
+    tokio::spawn(config_reload::watch(schedule_changed.clone()));
+
     // Spawn monitoring task
     let http = client.http.clone();
-    let _ = tokio::spawn( async move {
-        start_monitoring(&database.pool, &http, &config, &database).await;
+    let monitor_database = database.clone();
+    let monitor_shutdown = shutdown.clone();
+    tokio::spawn( async move {
+        // Catch up on what changed while the bot was offline before expiring anything
+        reconcile::reconcile_on_startup(&http, &Config::current(), monitor_database.as_ref()).await;
+        start_monitoring(&http, monitor_database.as_ref(), &schedule_changed, &monitor_shutdown).await;
     });
-    
-    // Run the bot
+
+    // Spawn shutdown task, running suspensions and expiries finish before the shards disconnect
+    let shard_manager = client.shard_manager.clone();
+    tokio::spawn( async move {
+        shutdown::wait_for_signal().await;
+        info!("Shutting down, waiting for running actions to finish");
+
+        if tokio::time::timeout(shutdown::SHUTDOWN_TIMEOUT, shutdown.wait_for_in_flight()).await.is_err() {
+            warn!("Running actions did not finish in time, shutting down anyway");
+        }
+
+        shard_manager.shutdown_all().await;
+    });
+
+    // Run the bot until it is shut down
     client.start().await.unwrap();
+
+    database.close().await;
+    info!("Shut down");
 }
\ No newline at end of file
@@ -0,0 +1,48 @@
+// Values are only recorded if the bot is built with the metrics feature, otherwise these do nothing
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+#[cfg(feature = "metrics")]
+mod server;
+
+#[cfg(feature = "metrics")]
+pub use server::serve;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+
+#[cfg(feature = "metrics")]
+use server::METRICS;
+
+pub fn command_run(command: &str) {
+    #[cfg(feature = "metrics")]
+    METRICS.commands.with_label_values(&[command]).inc();
+}
+
+// Count a failed request to Discord, the operation tells where it happened
+pub fn discord_error(operation: &str) {
+    #[cfg(feature = "metrics")]
+    METRICS.discord_errors.with_label_values(&[operation]).inc();
+}
+
+// Count a message sent to an event log channel, or the error if sending failed
+pub fn event_log_message<T>(result: &Result<T, serenity::Error>) {
+    match result {
+        Ok(_) => {
+            #[cfg(feature = "metrics")]
+            METRICS.event_log_messages.inc();
+        }
+        Err(_) => discord_error("event_log"),
+    }
+}
+
+// How late a suspension ended compared to its planned end
+pub fn suspension_expired(until: DateTime<Utc>) {
+    #[cfg(feature = "metrics")]
+    METRICS.expiry_lag.observe((Utc::now() - until).num_milliseconds().max(0) as f64 / 1000.0);
+}
+
+// The monitoring loop is still running
+pub fn monitor_heartbeat() {
+    #[cfg(feature = "metrics")]
+    METRICS.monitor_heartbeat.store(Utc::now().timestamp(), std::sync::atomic::Ordering::SeqCst);
+}
@@ -0,0 +1,149 @@
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use poise::serenity_prelude::{ConnectionStage, ShardManager};
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{error, info};
+use crate::db::SuspensionRepository;
+use crate::Error;
+
+// The monitor wakes up at least every monitoring interval, give it some time on top
+const HEARTBEAT_GRACE_IN_SECONDS: i64 = 30;
+
+pub(super) struct Metrics {
+    registry: Registry,
+    pub(super) commands: IntCounterVec,
+    pub(super) suspensions_active: IntGaugeVec,
+    pub(super) expiry_lag: Histogram,
+    pub(super) discord_errors: IntCounterVec,
+    pub(super) event_log_messages: IntCounter,
+    // Unix timestamp of the last monitoring loop, 0 until it started
+    pub(super) monitor_heartbeat: AtomicI64,
+}
+
+pub(super) static METRICS: Lazy<Metrics> = Lazy::new(|| {
+
+    let registry = Registry::new();
+
+    let commands = IntCounterVec::new(Opts::new("lsp_commands_total", "Slash commands run"), &["command"]).unwrap();
+    let suspensions_active = IntGaugeVec::new(Opts::new("lsp_suspensions_active", "Active suspensions"), &["guild"]).unwrap();
+    let expiry_lag = Histogram::with_opts(
+        HistogramOpts::new("lsp_expiry_lag_seconds", "How late suspensions ended after their planned end")
+            .buckets(vec![1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0]),
+    ).unwrap();
+    let discord_errors = IntCounterVec::new(Opts::new("lsp_discord_api_errors_total", "Failed requests to Discord"), &["operation"]).unwrap();
+    let event_log_messages = IntCounter::new("lsp_event_log_messages_total", "Messages sent to event log channels").unwrap();
+
+    registry.register(Box::new(commands.clone())).unwrap();
+    registry.register(Box::new(suspensions_active.clone())).unwrap();
+    registry.register(Box::new(expiry_lag.clone())).unwrap();
+    registry.register(Box::new(discord_errors.clone())).unwrap();
+    registry.register(Box::new(event_log_messages.clone())).unwrap();
+
+    Metrics { registry, commands, suspensions_active, expiry_lag, discord_errors, event_log_messages, monitor_heartbeat: AtomicI64::new(0) }
+});
+
+#[derive(Clone)]
+struct ServerState {
+    database: Arc<dyn SuspensionRepository>,
+    shard_manager: Arc<ShardManager>,
+    monitoring_interval_in_seconds: i64,
+}
+
+// Serve /healthz and /metrics on the given address until the bot stops
+pub async fn serve(listen: &str, database: Arc<dyn SuspensionRepository>, shard_manager: Arc<ShardManager>, monitoring_interval_in_seconds: u64) -> Result<(), Error> {
+
+    let state = ServerState { database, shard_manager, monitoring_interval_in_seconds: monitoring_interval_in_seconds as i64 };
+    let app = Router::new()
+        .route("/healthz", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(state);
+
+    let listener = TcpListener::bind(listen).await?;
+    info!(address = %listener.local_addr()?, "Serving metrics");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+// 200 if everything is fine, 503 with the failing checks otherwise
+async fn health(State(state): State<ServerState>) -> impl IntoResponse {
+
+    let runners = state.shard_manager.runners.lock().await;
+    let gateway = !runners.is_empty() && runners.values().all(|runner| runner.stage == ConnectionStage::Connected);
+    drop(runners);
+
+    let database = state.database.count_active_suspensions().await.is_ok();
+    let monitor = monitor_is_alive(METRICS.monitor_heartbeat.load(Ordering::SeqCst), Utc::now().timestamp(), state.monitoring_interval_in_seconds);
+
+    let status = if gateway && database && monitor { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let body = serde_json::json!({ "gateway": gateway, "database": database, "monitor": monitor });
+
+    (status, [(header::CONTENT_TYPE, "application/json")], body.to_string())
+}
+
+async fn metrics(State(state): State<ServerState>) -> impl IntoResponse {
+
+    // Counted on request, so the numbers always match the database
+    match state.database.count_active_suspensions_by_guild().await {
+        Ok(counts) => {
+            METRICS.suspensions_active.reset();
+            for (guild_id, count) in counts {
+                METRICS.suspensions_active.with_label_values(&[guild_id.to_string().as_str()]).set(count);
+            }
+        }
+        Err(error) => error!(%error, "Failed to count active suspensions for metrics"),
+    }
+
+    render()
+}
+
+fn render() -> impl IntoResponse {
+
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+
+    if let Err(error) = encoder.encode(&METRICS.registry.gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, [(header::CONTENT_TYPE, "text/plain")], error.to_string().into_bytes());
+    }
+
+    (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; version=0.0.4")], buffer)
+}
+
+fn monitor_is_alive(heartbeat: i64, now: i64, monitoring_interval_in_seconds: i64) -> bool {
+    heartbeat > 0 && now - heartbeat <= monitoring_interval_in_seconds + HEARTBEAT_GRACE_IN_SECONDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[test]
+    fn monitor_has_to_beat_within_its_interval() {
+        assert!(!monitor_is_alive(0, 1000, 60));
+        assert!(monitor_is_alive(1000, 1050, 60));
+        assert!(!monitor_is_alive(1000, 1100, 60));
+    }
+
+    #[tokio::test]
+    async fn metrics_are_rendered_for_prometheus() {
+
+        crate::metrics::command_run("suspend");
+        crate::metrics::discord_error("expiry");
+
+        let body = to_bytes(render().into_response().into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("lsp_commands_total{command=\"suspend\"}"));
+        assert!(body.contains("lsp_discord_api_errors_total{operation=\"expiry\"}"));
+        assert!(body.contains("# TYPE lsp_expiry_lag_seconds histogram"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use poise::serenity_prelude::{ChannelId, CreateMessage, GuildId, Http, Member, Mentionable, RoleId, UserId};
+use tracing::{error, warn};
+use crate::config::{Config, GuildConfig, ReconcileMode};
+use crate::db::{SuspensionFilter, SuspensionRepository};
+use crate::Error;
+
+// Discord returns at most this many members per request
+const MEMBERS_PER_REQUEST: u64 = 1000;
+
+// Keep reports within Discord's message limit
+const MAX_REPORT_LENGTH: usize = 1800;
+
+// Members whose roles don't match the active suspensions of a guild
+pub struct Reconciliation {
+    // Have the suspended role, but no active suspension
+    pub role_without_suspension: Vec<Member>,
+    // Have an active suspension, but not the suspended role
+    pub suspension_without_role: Vec<Member>,
+    // Members that could not be fixed and why
+    pub failures: Vec<String>,
+}
+
+impl Reconciliation {
+    pub fn is_empty(&self) -> bool {
+        self.role_without_suspension.is_empty() && self.suspension_without_role.is_empty()
+    }
+
+    pub fn report(&self, fixed: bool) -> String {
+
+        if self.is_empty() {
+            return String::from(":white_check_mark: Roles and active suspensions match");
+        }
+
+        let mut report = String::from("### Reconciliation");
+
+        for (title, members) in [
+            (if fixed { "Removed the suspended role, no active suspension" } else { "Suspended role without active suspension" }, &self.role_without_suspension),
+            (if fixed { "Gave the suspended role back, suspension is active" } else { "Active suspension without suspended role" }, &self.suspension_without_role),
+        ] {
+            if members.is_empty() {
+                continue;
+            }
+
+            let mentions: Vec<String> = members.iter().map(|member| member.mention().to_string()).collect();
+            report += format!("\r\n**{}** ({})\r\n{}", title, members.len(), mentions.join(", ")).as_str();
+        }
+
+        for failure in &self.failures {
+            report += format!("\r\n:x: {}", failure).as_str();
+        }
+
+        if report.len() > MAX_REPORT_LENGTH {
+            report = report.chars().take(MAX_REPORT_LENGTH).collect::<String>() + "...";
+        }
+
+        report
+    }
+}
+
+// Compare the members holding the suspended role with the active suspensions, optionally fix the roles
+pub async fn reconcile(http: &Http, guild_config: &GuildConfig, db: &dyn SuspensionRepository, fix: bool) -> Result<Reconciliation, Error> {
+
+    let guild_id = GuildId::new(guild_config.id);
+    let suspended_role = RoleId::new(guild_config.roles.suspended);
+
+    let members = get_all_members(http, guild_id).await?;
+    let suspended_user_ids: HashSet<UserId> = db.find_suspensions(&SuspensionFilter { guild_id: guild_config.id as i64, active_only: true, ..Default::default() })
+        .await?
+        .iter()
+        .map(|suspension| UserId::new(suspension.user_id as u64))
+        .collect();
+
+    let mut reconciliation = Reconciliation {
+        role_without_suspension: vec![],
+        suspension_without_role: vec![],
+        failures: vec![],
+    };
+
+    // Members who left the server can't be compared and are skipped
+    for member in members.into_values() {
+        let has_role = member.roles.contains(&suspended_role);
+        let is_suspended = suspended_user_ids.contains(&member.user.id);
+
+        if has_role && !is_suspended {
+            if fix {
+                if let Err(error) = member.remove_role(http, suspended_role).await {
+                    reconciliation.failures.push(format!("Could not remove the suspended role from {}: {}", member.mention(), error));
+                }
+            }
+            reconciliation.role_without_suspension.push(member);
+        } else if !has_role && is_suspended {
+            if fix {
+                if let Err(error) = member.add_role(http, suspended_role).await {
+                    reconciliation.failures.push(format!("Could not give the suspended role to {}: {}", member.mention(), error));
+                }
+            }
+            reconciliation.suspension_without_role.push(member);
+        }
+    }
+
+    Ok(reconciliation)
+}
+
+// Reconcile every guild as configured and report differences to the staff log
+pub async fn reconcile_on_startup(http: &Http, config: &Config, db: &dyn SuspensionRepository) {
+
+    for guild_config in &config.guilds {
+
+        let fix = match guild_config.reconcile_on_startup {
+            ReconcileMode::Off => continue,
+            ReconcileMode::Report => false,
+            ReconcileMode::Fix => true,
+        };
+
+        let reconciliation = match reconcile(http, guild_config, db, fix).await {
+            Ok(reconciliation) => reconciliation,
+            Err(error) => {
+                error!(guild_id = guild_config.id, %error, "Failed to reconcile guild");
+                continue;
+            }
+        };
+
+        if reconciliation.is_empty() {
+            continue;
+        }
+
+        let staff_log_channel_id = ChannelId::new(guild_config.channels.ban_log_staff);
+        let message = CreateMessage::default().content(reconciliation.report(fix));
+
+        if let Err(error) = staff_log_channel_id.send_message(http, message).await {
+            warn!(guild_id = guild_config.id, %error, "Failed to send message to staff log channel");
+        }
+    }
+}
+
+// Listing members requires the server members intent to be enabled for the bot
+async fn get_all_members(http: &Http, guild_id: GuildId) -> Result<HashMap<UserId, Member>, Error> {
+
+    let mut members = HashMap::new();
+    let mut after = None;
+
+    loop {
+        let page = guild_id.members(http, Some(MEMBERS_PER_REQUEST), after).await?;
+        let page_length = page.len() as u64;
+
+        after = page.last().map(|member| member.user.id);
+        members.extend(page.into_iter().map(|member| (member.user.id, member)));
+
+        if page_length < MEMBERS_PER_REQUEST {
+            return Ok(members);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(user_id: u64) -> Member {
+        let mut member = Member::default();
+        member.user.id = UserId::new(user_id);
+        member
+    }
+
+    #[test]
+    fn report_lists_both_directions() {
+
+        let reconciliation = Reconciliation {
+            role_without_suspension: vec![member(2), member(3)],
+            suspension_without_role: vec![],
+            failures: vec![String::from("Could not remove the suspended role from <@3>: Missing Permissions")],
+        };
+
+        let report = reconciliation.report(false);
+        assert!(report.contains("**Suspended role without active suspension** (2)\r\n<@2>, <@3>"));
+        assert!(!report.contains("Active suspension without suspended role"));
+        assert!(report.contains(":x: Could not remove"));
+
+        assert!(reconciliation.report(true).contains("Removed the suspended role"));
+    }
+
+    #[test]
+    fn matching_roles_are_reported_as_such() {
+
+        let reconciliation = Reconciliation { role_without_suspension: vec![], suspension_without_role: vec![], failures: vec![] };
+
+        assert!(reconciliation.is_empty());
+        assert!(reconciliation.report(true).contains("match"));
+    }
+}
@@ -0,0 +1,390 @@
+use chrono::{DateTime, Duration, Utc};
+use poise::serenity_prelude::{ActionRowComponent, ButtonStyle, ChannelId, Colour, ComponentInteraction, Context, CreateActionRow, CreateButton, CreateEmbed, CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateModal, Http, InputTextStyle, Interaction, Member, Mentionable, ModalInteraction, RoleId, UserId};
+use tokio::sync::Notify;
+use tracing::{error, warn};
+use crate::config::{Config, GuildConfig};
+use crate::db::{ActionSource, Suspension, SuspensionAction, SuspensionEvent, SuspensionFilter, SuspensionRepository};
+use crate::helper::{datetime_to_discord_timestamp, parse_duration};
+use crate::shutdown::Shutdown;
+use crate::{metrics, Error};
+
+// Custom ids of the buttons and the extend dialog, followed by the suspension id
+const EXTEND_BUTTON: &str = "reminder-extend:";
+const EXPIRE_BUTTON: &str = "reminder-expire:";
+const EXTEND_MODAL: &str = "reminder-extend-by:";
+const DURATION_INPUT: &str = "duration";
+
+// Send the reminders that are due, returns when the next one will be due
+pub async fn send_due_reminders(http: &Http, config: &Config, db: &dyn SuspensionRepository) -> Option<DateTime<Utc>> {
+
+    let now = Utc::now();
+    let mut next_reminder = None;
+
+    for guild_config in &config.guilds {
+
+        let reminders = guild_config.get_reminders();
+
+        if reminders.is_empty() {
+            continue;
+        }
+
+        let filter = SuspensionFilter { guild_id: guild_config.id as i64, active_only: true, ..Default::default() };
+        let suspensions = match db.find_suspensions(&filter).await {
+            Ok(suspensions) => suspensions,
+            Err(error) => {
+                error!(guild_id = guild_config.id, %error, "Failed to get active suspensions for reminders");
+                continue;
+            }
+        };
+
+        for suspension in suspensions {
+
+            let (due, next) = due_reminders(&suspension, &reminders, now);
+            next_reminder = earliest(next_reminder, next);
+
+            // Recorded before sending, a reminder must rather get lost than be sent over and over
+            let mut unsent = false;
+
+            for reminder in due {
+                match db.record_reminder(suspension.id, reminder.num_seconds(), now).await {
+                    Ok(recorded) => unsent |= recorded,
+                    Err(error) => error!(suspension_id = suspension.id, guild_id = suspension.guild_id, user_id = suspension.user_id, %error, "Failed to record reminder"),
+                }
+            }
+
+            // Several reminders can be due at once after a downtime, one message is enough
+            if !unsent {
+                continue;
+            }
+
+            let message = CreateMessage::default()
+                .embed(reminder_embed(&suspension))
+                .components(vec![reminder_buttons(suspension.id)]);
+
+            if let Err(error) = ChannelId::new(guild_config.channels.ban_log_staff).send_message(http, message).await {
+                warn!(suspension_id = suspension.id, guild_id = suspension.guild_id, user_id = suspension.user_id, %error, "Failed to send reminder to staff log channel");
+                metrics::discord_error("reminder");
+            }
+        }
+    }
+
+    next_reminder
+}
+
+// Reminders of a suspension that are due now, and when the next one will be due
+fn due_reminders(suspension: &Suspension, reminders: &[Duration], now: DateTime<Utc>) -> (Vec<Duration>, Option<DateTime<Utc>>) {
+
+    let mut due = vec![];
+    let mut next = None;
+
+    // Ended suspensions are up to the monitor
+    if suspension.until_datetime <= now {
+        return (due, next);
+    }
+
+    for reminder in reminders {
+        let remind_at = suspension.until_datetime - *reminder;
+
+        // Suspensions shorter than the reminder don't get it
+        if remind_at <= suspension.from_datetime {
+            continue;
+        }
+
+        if remind_at <= now {
+            due.push(*reminder);
+        } else {
+            next = earliest(next, Some(remind_at));
+        }
+    }
+
+    (due, next)
+}
+
+fn earliest(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn reminder_embed(suspension: &Suspension) -> CreateEmbed {
+
+    let role_mentions: Vec<String> = suspension.removed_roles.iter()
+        .map(|role| RoleId::new(role.role_id as u64).mention().to_string())
+        .collect();
+
+    CreateEmbed::default()
+        .title("Suspension Ending Soon")
+        .color(Colour::ORANGE)
+        .field("User", UserId::new(suspension.user_id as u64).mention().to_string(), false)
+        // Mention by id, forgotten moderators are stored as 0 which is no valid UserId
+        .field("Issued by", format!("<@{}>", suspension.moderator_id), false)
+        .field("Since", datetime_to_discord_timestamp(&suspension.from_datetime), true)
+        .field("Until", format!("{} (<t:{}:R>)", datetime_to_discord_timestamp(&suspension.until_datetime), suspension.until_datetime.timestamp()), true)
+        .field("Reason", suspension.reason.as_deref().unwrap_or("Not specified"), false)
+        .field("Removed roles", if role_mentions.is_empty() { String::from("None") } else { role_mentions.join(", ") }, false)
+}
+
+fn reminder_buttons(suspension_id: i64) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}{}", EXTEND_BUTTON, suspension_id)).label("Extend").style(ButtonStyle::Primary),
+        CreateButton::new(format!("{}{}", EXPIRE_BUTTON, suspension_id)).label("Let it expire").style(ButtonStyle::Secondary),
+    ])
+}
+
+// Handle the buttons of reminders and the extend dialog, other interactions are ignored
+pub async fn handle_interaction(ctx: &Context, interaction: &Interaction, db: &dyn SuspensionRepository, schedule_changed: &Notify, shutdown: &Shutdown) -> Result<(), Error> {
+    match interaction {
+        Interaction::Component(component) => handle_button(ctx, component, db).await,
+        Interaction::Modal(modal) => handle_extend(ctx, modal, db, schedule_changed, shutdown).await,
+        _ => Ok(()),
+    }
+}
+
+async fn handle_button(ctx: &Context, component: &ComponentInteraction, db: &dyn SuspensionRepository) -> Result<(), Error> {
+
+    let custom_id = component.data.custom_id.as_str();
+    let (extend, suspension_id) = match (parse_custom_id(custom_id, EXTEND_BUTTON), parse_custom_id(custom_id, EXPIRE_BUTTON)) {
+        (Some(suspension_id), _) => (true, suspension_id),
+        (_, Some(suspension_id)) => (false, suspension_id),
+        _ => return Ok(()),
+    };
+
+    let config = Config::current();
+    let Some(guild_config) = component.guild_id.and_then(|guild_id| config.get_guild_config(guild_id.get())) else {
+        return Ok(());
+    };
+
+    if !is_permitted(guild_config, component.member.as_ref()) {
+        component.create_response(ctx, ephemeral(":x: You don't have permission to do that!")).await?;
+        return Ok(());
+    }
+
+    let suspension = db.get_suspension(suspension_id).await?;
+
+    if !belongs_to_guild(suspension.as_ref(), guild_config) {
+        component.create_response(ctx, ephemeral(":x: You don't have permission to do that!")).await?;
+        return Ok(());
+    }
+
+    // Someone may have lifted it since the reminder was sent
+    let Some(suspension) = suspension.filter(|suspension| suspension.active == Some(true)) else {
+        component.create_response(ctx, resolved(String::from(":sparkles: This suspension has already ended"))).await?;
+        return Ok(());
+    };
+
+    if extend {
+
+        let input = CreateInputText::new(InputTextStyle::Short, "Extend by", DURATION_INPUT).placeholder("e.g. 12h, 3d or 1w");
+        let modal = CreateModal::new(format!("{}{}", EXTEND_MODAL, suspension.id), "Extend suspension")
+            .components(vec![CreateActionRow::InputText(input)]);
+
+        component.create_response(ctx, CreateInteractionResponse::Modal(modal)).await?;
+    } else {
+        component.create_response(ctx, resolved(format!(
+            ":hourglass: {} lets the suspension end {}", component.user.mention(), datetime_to_discord_timestamp(&suspension.until_datetime)
+        ))).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle_extend(ctx: &Context, modal: &ModalInteraction, db: &dyn SuspensionRepository, schedule_changed: &Notify, shutdown: &Shutdown) -> Result<(), Error> {
+
+    let Some(suspension_id) = parse_custom_id(&modal.data.custom_id, EXTEND_MODAL) else {
+        return Ok(());
+    };
+
+    let config = Config::current();
+    let Some(guild_config) = modal.guild_id.and_then(|guild_id| config.get_guild_config(guild_id.get())) else {
+        return Ok(());
+    };
+
+    let Some(member) = modal.member.as_ref().filter(|member| is_permitted(guild_config, Some(member))) else {
+        modal.create_response(ctx, ephemeral(":x: You don't have permission to do that!")).await?;
+        return Ok(());
+    };
+
+    let Some(_in_flight) = shutdown.begin() else {
+        modal.create_response(ctx, ephemeral(":zzz: The bot is restarting, please try again in a moment!")).await?;
+        return Ok(());
+    };
+
+    let input = modal.data.components.iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == DURATION_INPUT => input.value.clone(),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let input = input.trim();
+
+    let suspension = db.get_suspension(suspension_id).await?;
+
+    if !belongs_to_guild(suspension.as_ref(), guild_config) {
+        modal.create_response(ctx, ephemeral(":x: You don't have permission to do that!")).await?;
+        return Ok(());
+    }
+
+    let Some(suspension) = suspension.filter(|suspension| suspension.active == Some(true)) else {
+        modal.create_response(ctx, resolved(String::from(":sparkles: This suspension has already ended"))).await?;
+        return Ok(());
+    };
+
+    let Some(until) = parse_duration(input).and_then(|length| suspension.until_datetime.checked_add_signed(length)) else {
+        modal.create_response(ctx, ephemeral(":x: Invalid input format!")).await?;
+        return Ok(());
+    };
+
+    // Limits apply to the whole suspension, administrators are never limited
+    if !is_administrator(member) {
+        if let Some((max_duration, max_duration_string)) = guild_config.get_max_suspension_duration(&role_ids(member)) {
+            if until - suspension.from_datetime > max_duration {
+                modal.create_response(ctx, ephemeral(format!(":x: You can only suspend for up to **{}**!", max_duration_string))).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    let extended = db.extend_suspension(SuspensionEvent {
+        suspension_id,
+        action: SuspensionAction::Edit,
+        actor_id: Some(modal.user.id.get() as i64),
+        created_at: Utc::now(),
+        reason: Some(format!("Extended by {}", input)),
+        source: ActionSource::Reminder,
+    }, until).await?;
+
+    if !extended {
+        modal.create_response(ctx, resolved(String::from(":sparkles: This suspension has already ended"))).await?;
+        return Ok(());
+    }
+
+    schedule_changed.notify_one();
+
+    modal.create_response(ctx, resolved(format!(
+        ":calendar: {} extended the suspension by {} until {}", modal.user.mention(), input, datetime_to_discord_timestamp(&until)
+    ))).await?;
+
+    Ok(())
+}
+
+fn parse_custom_id(custom_id: &str, prefix: &str) -> Option<i64> {
+    custom_id.strip_prefix(prefix)?.parse().ok()
+}
+
+fn role_ids(member: &Member) -> Vec<u64> {
+    member.roles.iter().map(|role_id| role_id.get()).collect()
+}
+
+fn is_administrator(member: &Member) -> bool {
+    member.permissions.is_some_and(|permissions| permissions.administrator())
+}
+
+// Only members who may suspend decide about suspensions
+fn is_permitted(guild_config: &GuildConfig, member: Option<&Member>) -> bool {
+    member.is_some_and(|member| guild_config.may_suspend(&role_ids(member), is_administrator(member)))
+}
+
+// Custom ids come from the client, so they may point to a suspension of another guild
+fn belongs_to_guild(suspension: Option<&Suspension>, guild_config: &GuildConfig) -> bool {
+    suspension.is_none_or(|suspension| suspension.guild_id == guild_config.id as i64)
+}
+
+fn ephemeral(content: impl Into<String>) -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(CreateInteractionResponseMessage::new().content(content).ephemeral(true))
+}
+
+// Answer on the reminder itself and remove its buttons
+fn resolved(content: String) -> CreateInteractionResponse {
+    CreateInteractionResponse::UpdateMessage(CreateInteractionResponseMessage::new().content(content).components(vec![]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suspension(from: DateTime<Utc>, until: DateTime<Utc>) -> Suspension {
+        Suspension {
+            id: 1,
+            guild_id: 1,
+            user_id: 2,
+            moderator_id: 3,
+            removed_roles: vec![],
+            from_datetime: from,
+            until_datetime: until,
+            reason: None,
+            active: Some(true),
+            lifted_by: None,
+            lifted_at: None,
+            lift_reason: None,
+            expiry_attempts: 0,
+        }
+    }
+
+    #[test]
+    fn reminders_are_due_before_the_end() {
+
+        let now = Utc::now();
+        let reminders = [Duration::hours(24), Duration::hours(1)];
+
+        // A week long suspension with two days left, both reminders are still ahead
+        let (due, next) = due_reminders(&suspension(now - Duration::days(5), now + Duration::days(2)), &reminders, now);
+        assert!(due.is_empty());
+        assert_eq!(next, Some(now + Duration::days(1)));
+
+        // Twelve hours left, the first one is due
+        let (due, next) = due_reminders(&suspension(now - Duration::days(6), now + Duration::hours(12)), &reminders, now);
+        assert_eq!(due, vec![Duration::hours(24)]);
+        assert_eq!(next, Some(now + Duration::hours(11)));
+    }
+
+    #[test]
+    fn short_and_ended_suspensions_get_no_reminders() {
+
+        let now = Utc::now();
+        let reminders = [Duration::hours(24)];
+
+        // Ends in 12 hours but only lasts 18, so it never was 24 hours before its end
+        let (due, next) = due_reminders(&suspension(now - Duration::hours(6), now + Duration::hours(12)), &reminders, now);
+        assert!(due.is_empty());
+        assert!(next.is_none());
+
+        let (due, next) = due_reminders(&suspension(now - Duration::days(2), now - Duration::hours(1)), &reminders, now);
+        assert!(due.is_empty());
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn reminders_can_be_shown_for_forgotten_moderators() {
+
+        let now = Utc::now();
+        let mut suspension = suspension(now - Duration::days(1), now + Duration::days(1));
+        suspension.moderator_id = crate::db::ERASED_USER_ID;
+
+        let embed = serde_json::to_string(&reminder_embed(&suspension)).unwrap();
+        assert!(embed.contains("<@0>"));
+    }
+
+    #[test]
+    fn suspensions_of_other_guilds_are_refused() {
+
+        let guild_config: GuildConfig = toml::from_str(
+            "id = 1\nchannels = { ban_log = 2, ban_log_staff = 3, event_log = 4 }\nroles = { suspended = 5, suspend_permitted = [6] }"
+        ).unwrap();
+
+        let now = Utc::now();
+        let mut suspension = suspension(now - Duration::days(1), now + Duration::days(1));
+        assert!(belongs_to_guild(Some(&suspension), &guild_config));
+
+        suspension.guild_id = 7;
+        assert!(!belongs_to_guild(Some(&suspension), &guild_config));
+    }
+
+    #[test]
+    fn custom_ids_carry_the_suspension() {
+        assert_eq!(parse_custom_id("reminder-extend:42", EXTEND_BUTTON), Some(42));
+        assert_eq!(parse_custom_id("reminder-extend:42", EXPIRE_BUTTON), None);
+        assert_eq!(parse_custom_id("reminder-extend-by:42", EXTEND_BUTTON), None);
+    }
+}
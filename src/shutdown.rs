@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Longest wait for running actions before shutting down anyway
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Keeps track of actions that must not be interrupted, like suspending or ending a suspension
+#[derive(Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+// Marks an action as running until dropped
+pub struct InFlight<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Shutdown {
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    // Start an action, None once shutting down
+    pub fn begin(&self) -> Option<InFlight<'_>> {
+
+        // Count first, so wait_for_in_flight can't miss an action that starts right now
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight { shutdown: self };
+
+        if self.is_requested() {
+            return None;
+        }
+
+        Some(in_flight)
+    }
+
+    // Refuse new actions and wait until the running ones are done
+    pub async fn wait_for_in_flight(&self) {
+
+        self.requested.store(true, Ordering::SeqCst);
+
+        loop {
+            let idle = self.idle.notified();
+
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            idle.await;
+        }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.shutdown.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.shutdown.idle.notify_waiters();
+        }
+    }
+}
+
+// Wait for Ctrl+C, or SIGTERM from service managers and containers
+pub async fn wait_for_signal() {
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn shutdown_waits_for_running_actions() {
+
+        let shutdown = Arc::new(Shutdown::default());
+        let in_flight = shutdown.begin().unwrap();
+
+        let waiting = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.wait_for_in_flight().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        assert!(shutdown.begin().is_none());
+
+        drop(in_flight);
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{config_reload, Context, Error};
+
+/// Manages the config of the bot, bot owner only
+#[poise::command(slash_command, subcommands("reload"), subcommand_required, owners_only)]
+pub async fn config(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reloads config.toml without restarting the bot, an invalid file keeps the current config
+#[poise::command(slash_command, owners_only)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "config reload", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id))]
+pub async fn reload(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+
+    let content = match config_reload::reload() {
+        Ok(reload) => {
+            // The monitor may sleep with the old interval and reminders
+            ctx.data().schedule_changed.notify_one();
+
+            let mut content = format!(":arrows_counterclockwise: Reloaded config.toml with {} guild(s)", reload.guilds);
+            if reload.needs_restart {
+                content += "\r\n:warning: Database, metrics and manual_role_removal settings only apply after a restart";
+            }
+            content
+        }
+        Err(error) => format!(":x: config.toml is invalid, keeping the current config: {}", error),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
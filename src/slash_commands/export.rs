@@ -0,0 +1,77 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use poise::serenity_prelude as serenity;
+use crate::{export, helper, Context, Error};
+use crate::db::SuspensionFilter;
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ExportFormat {
+    #[name = "CSV"]
+    Csv,
+    #[name = "JSON"]
+    Json,
+}
+
+/// Exports the suspension history of this server as a file
+#[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "export", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id, user_id = user.as_ref().map(|user| user.id.get())))]
+pub async fn export(
+    ctx: Context<'_>,
+    #[description = "File format"] format: ExportFormat,
+    #[description = "Only suspensions of this user"] user: Option<serenity::User>,
+    #[description = "Only suspensions issued by this moderator"] moderator: Option<serenity::User>,
+    #[description = "Issued on or after (YYYY-MM-DD)"] from: Option<String>,
+    #[description = "Issued on or before (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Only active suspensions"] active_only: Option<bool>,
+) -> Result<(), Error> {
+
+    let author_member = &ctx.author_member().await.unwrap();
+
+    if !helper::member_has_suspension_permission(&ctx, author_member).await {
+        return Ok(());
+    }
+
+    // Evaluate the date range, until includes the whole day
+    let from_datetime = from.as_deref().map(|date| parse_date(date, NaiveTime::MIN));
+    let until_datetime = until.as_deref().map(|date| parse_date(date, NaiveTime::from_hms_opt(23, 59, 59).unwrap()));
+
+    if matches!(from_datetime, Some(None)) || matches!(until_datetime, Some(None)) {
+
+        ctx.send(
+            poise::CreateReply::default()
+                .content(":x: Invalid date, please use YYYY-MM-DD!")
+                .ephemeral(true)
+        ).await?;
+
+        return Ok(());
+    }
+
+    let filter = SuspensionFilter {
+        guild_id: ctx.guild_id().unwrap().get() as i64,
+        user_id: user.map(|user| user.id.get() as i64),
+        moderator_id: moderator.map(|moderator| moderator.id.get() as i64),
+        from: from_datetime.flatten(),
+        until: until_datetime.flatten(),
+        active_only: active_only.unwrap_or(false),
+    };
+
+    let db = &ctx.data().database;
+    let suspensions = db.find_suspensions(&filter).await?;
+
+    let (data, filename) = match format {
+        ExportFormat::Csv => (export::to_csv(&suspensions)?, "suspensions.csv"),
+        ExportFormat::Json => (export::to_json(&suspensions)?, "suspensions.json"),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(":file_folder: Exported {} suspension(s)", suspensions.len()))
+            .attachment(serenity::CreateAttachment::bytes(data, filename))
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
+
+fn parse_date(date: &str, time: NaiveTime) -> Option<DateTime<Utc>> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().map(|date| date.and_time(time).and_utc())
+}
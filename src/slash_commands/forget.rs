@@ -0,0 +1,56 @@
+use crate::{helper, Context, Error};
+
+/// Erases the suspension history of a user in this server, admin only
+#[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "forget", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id, user_id = %user_id))]
+pub async fn forget(
+    ctx: Context<'_>,
+    #[description = "Id of the user, they don't have to be on the server anymore"] user_id: String,
+) -> Result<(), Error> {
+
+    let author_member = &ctx.author_member().await.unwrap();
+
+    if !helper::member_is_administrator(&ctx, author_member).await {
+        return Ok(());
+    }
+
+    // Ids don't fit into Discord's integer options, so they come in as text
+    let Some(user_id) = user_id.trim().parse::<u64>().ok().filter(|user_id| *user_id > 0 && *user_id <= i64::MAX as u64) else {
+
+        ctx.send(
+            poise::CreateReply::default()
+                .content(":x: That is not a valid user id!")
+                .ephemeral(true)
+        ).await?;
+
+        return Ok(());
+    };
+
+    let guild_id = ctx.guild_id().unwrap().get() as i64;
+    let db = &ctx.data().database;
+
+    // Erasing an active suspension would leave the user without their roles
+    if !db.get_active_suspensions(guild_id, user_id as i64).await?.is_empty() {
+
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!(":x: <@{}> is currently suspended, lift the suspension first!", user_id))
+                .ephemeral(true)
+        ).await?;
+
+        return Ok(());
+    }
+
+    let report = db.forget_user(guild_id, user_id as i64).await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                ":wastebasket: Forgot <@{}>\r\n- Deleted {} suspension(s) with {} event(s)\r\n- Pseudonymized {} action(s) they took as moderator",
+                user_id, report.suspensions, report.events, report.pseudonymized
+            ))
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+use poise::serenity_prelude as serenity;
+use crate::{helper, import, Context, Error};
+use crate::db::SuspensionFilter;
+
+// Keep the report within Discord's message limit
+const MAX_REPORT_LENGTH: usize = 1800;
+
+/// Imports historical suspensions from a CSV or JSON file, admin only
+#[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "import", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id))]
+pub async fn import(
+    ctx: Context<'_>,
+    #[description = "CSV or JSON file with user_id, moderator_id, from_datetime, until_datetime and reason"] file: serenity::Attachment,
+    #[description = "Only check the file without importing anything"] dry_run: Option<bool>,
+) -> Result<(), Error> {
+
+    let author_member = &ctx.author_member().await.unwrap();
+
+    if !helper::member_is_administrator(&ctx, author_member).await {
+        return Ok(());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let data = file.download().await?;
+    let filename = file.filename.to_lowercase();
+
+    let rows = if filename.ends_with(".csv") {
+        import::from_csv(&data)
+    } else if filename.ends_with(".json") {
+        import::from_json(&data)
+    } else {
+        Err("the file has to end in .csv or .json".into())
+    };
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(error) => {
+
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!(":x: Could not read **{}**: {}", file.filename, error))
+                    .ephemeral(true)
+            ).await?;
+
+            return Ok(());
+        }
+    };
+
+    let guild_id = ctx.guild_id().unwrap().get() as i64;
+    let db = &ctx.data().database;
+
+    // A suspension counts as a duplicate if the user already has one with the same time span
+    let mut known: HashSet<(i64, i64, i64)> = db.find_suspensions(&SuspensionFilter { guild_id, ..Default::default() }).await?
+        .iter()
+        .map(|suspension| (suspension.user_id, suspension.from_datetime.timestamp(), suspension.until_datetime.timestamp()))
+        .collect();
+
+    let mut suspensions = Vec::new();
+    let mut invalid = Vec::new();
+    let mut duplicates = Vec::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        match import::to_suspension(row, guild_id) {
+            Ok(suspension) => {
+                if known.insert((suspension.user_id, suspension.from_datetime.timestamp(), suspension.until_datetime.timestamp())) {
+                    suspensions.push(suspension);
+                } else {
+                    duplicates.push(format!("Row {}: <@{}> from {}", index + 1, suspension.user_id, helper::datetime_to_discord_timestamp(&suspension.from_datetime)));
+                }
+            }
+            Err(error) => invalid.push(format!("Row {}: {}", index + 1, error)),
+        }
+    }
+
+    let mut report = if dry_run.unwrap_or(false) {
+        format!(":mag: **Dry run:** {} of {} suspension(s) would be imported", suspensions.len(), rows.len())
+    } else {
+        let imported = db.import_suspensions(&suspensions).await?;
+        format!(":white_check_mark: Imported {} of {} suspension(s)", imported, rows.len())
+    };
+
+    for (title, lines) in [("Invalid", &invalid), ("Duplicates", &duplicates)] {
+        if lines.is_empty() {
+            continue;
+        }
+
+        report += format!("\n### {} ({})", title, lines.len()).as_str();
+
+        for line in lines {
+            if report.len() + line.len() > MAX_REPORT_LENGTH {
+                report += "\n...";
+                break;
+            }
+
+            report += format!("\n- {}", line).as_str();
+        }
+    }
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(report)
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
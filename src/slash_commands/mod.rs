@@ -1,3 +1,10 @@
 pub(crate) mod suspend;
 pub(crate) mod suspension_history;
 pub(crate) mod remove_suspension;
+pub(crate) mod export;
+pub(crate) mod import;
+pub(crate) mod forget;
+pub(crate) mod snapshot;
+pub(crate) mod search;
+pub(crate) mod reconcile;
+pub(crate) mod config;
@@ -0,0 +1,36 @@
+use crate::{helper, reconcile, Context, Error};
+use crate::config::Config;
+
+/// Compares the suspended role with the active suspensions of this server
+#[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "reconcile", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id))]
+pub async fn reconcile(
+    ctx: Context<'_>,
+    #[description = "Give or remove the suspended role where it doesn't match"] fix: Option<bool>,
+) -> Result<(), Error> {
+
+    let author_member = &ctx.author_member().await.unwrap();
+
+    if !helper::member_has_suspension_permission(&ctx, author_member).await {
+        return Ok(());
+    }
+
+    ctx.defer_ephemeral().await?;
+
+    let config = &Config::current();
+    let guild_config = Config::get_guild_config(config, ctx.guild_id().unwrap().get()).unwrap();
+    let fix = fix.unwrap_or(false);
+
+    let content = match reconcile::reconcile(ctx.http(), guild_config, ctx.data().database.as_ref(), fix).await {
+        Ok(reconciliation) => reconciliation.report(fix),
+        Err(error) => format!(":x: Failed to compare roles and suspensions: {}", error),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
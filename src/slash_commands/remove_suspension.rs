@@ -1,16 +1,27 @@
+use chrono::Utc;
 use poise::serenity_prelude as serenity;
-use poise::serenity_prelude::Mentionable;
+use poise::serenity_prelude::{CreateEmbedAuthor, CreateMessage, EditMember, Mentionable};
+use tracing::{error, info, warn};
 use crate::{helper, Context, Error};
 use crate::config::Config;
+use crate::db::{ActionSource, SuspensionAction, SuspensionEvent};
 use crate::helper::restore_roles;
 
 /// Removes a users active suspension
 #[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "remove_suspension", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id, user_id = %user.id))]
 pub async fn remove_suspension (
     ctx: Context<'_>,
     #[description = "Selected user"] user: serenity::User,
+    #[description = "Reason"] reason: Option<String>,
 ) -> Result<(), Error> {
 
+    // Finish lifting even if the bot is asked to shut down meanwhile
+    let Some(_in_flight) = ctx.data().shutdown.begin() else {
+        helper::reply_restarting(&ctx).await?;
+        return Ok(());
+    };
+
     let author_member = &ctx.author_member().await.unwrap();
 
     if !helper::member_has_suspension_permission(&ctx, author_member).await {
@@ -22,21 +33,107 @@ pub async fn remove_suspension (
     let guild_id = guild.get();
     let suspensions = db.get_active_suspensions(guild_id as i64, user.id.get() as i64).await?;
     let member = guild.member(ctx, user.id).await?;
-    let config = &ctx.data().config;
-    let guild_config = Config::get_guild_config(&config, guild_id).unwrap();
+    let config = &Config::current();
+    let guild_config = Config::get_guild_config(config, guild_id).unwrap();
     let suspended_role_id = guild_config.roles.suspended;
 
     for suspension in &suspensions {
-        // Try to restore roles
-        restore_roles(ctx.http(), guild, suspended_role_id, &suspension).await.expect(format!("Unable to restore roles for user id {}", suspension.user_id).as_str());
-        db.set_suspension_inactive(suspension.id).await;
+
+        // Roles first, the suspension only ends once they are back
+        let previous_roles = match restore_roles(ctx.http(), guild, suspended_role_id, suspension).await {
+            Ok(previous_roles) => previous_roles,
+            Err(error) => {
+
+                ctx.send(
+                    poise::CreateReply::default()
+                        .content(format!(":x: Could not restore the roles of {}, the suspension is still active: {}", member.mention(), error))
+                        .ephemeral(true)
+                ).await?;
+
+                return Ok(());
+            }
+        };
+
+        let lifted = db.set_suspension_inactive(SuspensionEvent {
+            suspension_id: suspension.id,
+            action: SuspensionAction::Lift,
+            actor_id: Some(ctx.author().id.get() as i64),
+            created_at: Utc::now(),
+            reason: reason.clone(),
+            source: ActionSource::Command,
+        }).await;
+
+        // Put the suspended role back, so Discord matches the still active suspension
+        if let Err(error) = lifted {
+
+            let content = match guild.edit_member(ctx, user.id, EditMember::new().roles(previous_roles)).await {
+                Ok(_) => format!(":x: Could not lift the suspension of {}, nothing was changed: {}", member.mention(), error),
+                Err(rollback_error) => {
+                    error!(suspension_id = suspension.id, error = %rollback_error, "Failed to give the suspended role back");
+                    format!(":x: Could not lift the suspension of {}: {}\r\nTheir roles were restored anyway, the suspension is still active though!", member.mention(), error)
+                }
+            };
+
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(content)
+                    .ephemeral(true)
+            ).await?;
+
+            return Ok(());
+        }
+
+        ctx.data().schedule_changed.notify_one();
+        info!(suspension_id = suspension.id, "Suspension lifted");
     }
 
-    if suspensions.len() > 0 {
-        ctx.reply(format!(":broken_chain: {} is no longer suspended!", member.mention())).await?;
-    } else {
+    if suspensions.is_empty() {
         ctx.reply(format!(":sparkles: {} has no active suspensions!", member.mention())).await?;
+        return Ok(());
     }
 
+    let reason_string = reason.unwrap_or_else(|| String::from("Not specified"));
+
+    // Get the log channel id's from guild config
+    let log_channel_id = guild_config.channels.ban_log;
+    let staff_log_channel_id = guild_config.channels.ban_log_staff;
+
+    // Try to get the public log channel
+    if let Some(tuple) = guild.channels(&ctx).await.unwrap().iter().find(|tuple| {*tuple.0 == log_channel_id}) {
+
+        // Send a message
+        tuple.1.send_message(&ctx, CreateMessage::default().content(
+            format!("### Suspension Lifted\r\nName: {}\r\nReason: **{}**", user.mention(), &reason_string),
+        )).await?;
+
+    } else {
+        let guild_name = &guild.name(ctx).unwrap();
+        warn!(guild_name, "Unable to find log channel");
+    }
+
+    // Send embed to staff log channel with more information
+    if let Some(tuple) = guild.channels(&ctx).await.unwrap().iter().find(|tuple| {*tuple.0 == staff_log_channel_id}) {
+
+        let avatar_url = user.avatar_url().unwrap_or_else(|| user.default_avatar_url());
+
+        // Create an embed
+        let embed = serenity::CreateEmbed::default()
+            .title("Suspension Lifted")
+            .author(CreateEmbedAuthor::new(&user.name).icon_url(avatar_url))
+            .color(serenity::Colour::DARK_GREEN)
+            .field("User", user.mention().to_string(), false)
+            .field("Lifted by", author_member.mention().to_string(), false)
+            .field("Was until", helper::datetime_to_discord_timestamp(&suspensions[0].until_datetime), false)
+            .field("Reason", &reason_string, true);
+
+        // Send the embed
+        tuple.1.send_message(&ctx, CreateMessage::default().embed(embed)).await?;
+    } else {
+        let guild_name = &guild.name(ctx).unwrap();
+        warn!(guild_name, "Unable to find staff log channel");
+    }
+
+    ctx.reply(format!(":broken_chain: {} is no longer suspended!", member.mention())).await?;
+
     Ok(())
 }
@@ -0,0 +1,59 @@
+use crate::{Context, Error};
+use crate::helper;
+
+const MAX_RESULTS: i64 = 10;
+
+// The query is echoed in the reply, so long ones are shortened
+const MAX_QUERY_LENGTH: usize = 100;
+
+/// Searches the reasons of all suspensions in this server
+#[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "search", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id))]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Words that have to appear in the reason"] query: String,
+) -> Result<(), Error> {
+
+    let author_member = &ctx.author_member().await.unwrap();
+
+    if !helper::member_has_suspension_permission(&ctx, author_member).await {
+        return Ok(());
+    }
+
+    let db = &ctx.data().database;
+    let guild_id = ctx.guild_id().unwrap().get();
+    let hits = db.search_suspensions(guild_id as i64, &query, MAX_RESULTS).await?;
+
+    let shown_query = helper::truncate(&query, MAX_QUERY_LENGTH);
+    let mut header = format!("## :mag: Suspensions matching \"{}\"\r\n", shown_query);
+
+    if hits.is_empty() {
+        header = format!(":mag: No suspension matches \"{}\"", shown_query);
+    }
+
+    let mut blocks = vec![];
+
+    for (count, hit) in (1..).zip(hits) {
+
+        let suspension = &hit.suspension;
+
+        blocks.push(format!("\r\n### {count}. <@{}> {}\r\nIssued by <@{}> on {}\r\n> {}",
+                            suspension.user_id,
+                            { if suspension.active.unwrap_or(false) {"(Active)"} else {""} },
+                            suspension.moderator_id,
+                            helper::datetime_to_discord_timestamp(&suspension.from_datetime),
+                            hit.snippet.replace('\n', " ")
+        ));
+    }
+
+    // Hits that don't fit into one message are only counted
+    let message = helper::paginate(header, blocks, 1, "hit(s)").remove(0);
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use crate::{backup, Context, Error};
+use crate::config::Config;
+
+/// Saves a snapshot of the database right now, bot owner only
+#[poise::command(slash_command, owners_only)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "snapshot", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id))]
+pub async fn snapshot(
+    ctx: Context<'_>,
+) -> Result<(), Error> {
+
+    ctx.defer_ephemeral().await?;
+
+    let data = ctx.data();
+    let content = match backup::take_snapshot(data.database.as_ref(), &Config::current().database.backup).await {
+        Ok(path) => format!(":floppy_disk: Saved snapshot **{}**", path.file_name().unwrap_or_default().to_string_lossy()),
+        Err(error) => format!(":x: Failed to save snapshot: {}", error),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(content)
+            .ephemeral(true)
+    ).await?;
+
+    Ok(())
+}
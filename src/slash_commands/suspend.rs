@@ -1,14 +1,15 @@
-use chrono::{Duration, Local};
+use chrono::{SubsecRound, Utc};
 use poise::serenity_prelude as serenity;
-use poise::serenity_prelude::{CreateEmbedAuthor, CreateMessage, Mentionable};
-use regex::Regex;
+use poise::serenity_prelude::{CreateEmbedAuthor, CreateMessage, EditMember, Mentionable, RoleId};
+use tracing::{error, info, warn};
 use crate::{Context, Error};
 use crate::config::Config;
-use crate::db::Suspension;
+use crate::db::{ActionSource, RemovedRole, Suspension, SuspensionAction, SuspensionEvent};
 use crate::helper;
 
 /// Suspends a user for a duration
 #[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "suspend", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id, user_id = %user.id, suspension_id = tracing::field::Empty))]
 pub async fn suspend(
     ctx: Context<'_>,
     #[description = "Selected user"] user: serenity::User,
@@ -16,6 +17,12 @@ pub async fn suspend(
     #[description = "Reason"] reason: Option<String>,
 ) -> Result<(), Error> {
     
+    // Finish suspending even if the bot is asked to shut down meanwhile
+    let Some(_in_flight) = ctx.data().shutdown.begin() else {
+        helper::reply_restarting(&ctx).await?;
+        return Ok(());
+    };
+
     let author_member = &ctx.author_member().await.unwrap();
     
     // Check if author has suspension permission
@@ -25,43 +32,32 @@ pub async fn suspend(
     
     // Check if the user has an active suspension
     if helper::user_is_suspended(&ctx, &user).await {
-        
-        ctx.send(
-            poise::CreateReply::default()
-                .content(format!(":x: {} is already suspended!", user.mention()))
-                .ephemeral(true)
-        ).await?;
-
         return Ok(());
     }
     
     // Evaluate the duration
-    let re = Regex::new(r"^(\d+)([shdwm])$").unwrap();
-    
-    if let Some(caps) = re.captures(duration.as_str()) {
-        let value: i64 = caps[1].parse().unwrap(); // Extract number
-        let unit = &caps[2]; // Extract time unit ('d', 'w', or 'm')
-
-        let now = Local::now().naive_local();
-        let until = match unit {
-            "s" => now + Duration::seconds(value),
-            "h" => now + Duration::hours(value),
-            "d" => now + Duration::days(value),
-            "w" => now + Duration::weeks(value),
-            "m" => now + Duration::days(value * 30), // Approximation of a month (30 days)
-            _ => {
-                println!("Unknown unit: {}", unit);
-                now
-            }
-        };
+    if let Some(length) = helper::parse_duration(duration.as_str()) {
+
+        // Check if the duration exceeds the author's limit
+        if !helper::duration_within_limit(&ctx, author_member, length).await {
+            return Ok(());
+        }
+
+        let now = Utc::now().trunc_subsecs(0);
+        let until = now + length;
 
         let guild = ctx.guild_id().unwrap();
-        let guild_member = guild.member(&ctx, user.id).await.unwrap();
-        let roles = &guild_member.roles;
-        let role_ids: Vec<String> = roles.iter().map(|role_id| role_id.get().to_string()).collect();
+        let mut guild_member = guild.member(&ctx, user.id).await.unwrap();
+        let roles = guild_member.roles.clone();
+        let guild_roles = guild.roles(&ctx).await?;
+        let removed_roles: Vec<RemovedRole> = roles.iter()
+            .map(|role_id| RemovedRole {
+                role_id: role_id.get() as i64,
+                role_name: guild_roles.get(role_id).map(|role| role.name.clone()),
+            })
+            .collect();
         let db = &ctx.data().database;
 
-        let until_string = &until.format("%Y-%m-%d %H:%M:%S").to_string();
         let reason_string = reason.clone().unwrap_or_else(|| String::from("Not specified"));
         
         let suspension = Suspension {
@@ -69,22 +65,76 @@ pub async fn suspend(
             guild_id: guild.get() as i64,
             user_id: user.id.get() as i64,
             moderator_id: ctx.author().id.get() as i64,
-            previous_roles: role_ids,
-            from_datetime: now.format("%Y-%m-%d %H:%M:%S").to_string(),
-            until_datetime: until_string.to_string(),
-            reason: reason,
+            removed_roles,
+            from_datetime: now,
+            until_datetime: until,
+            reason,
             active: None,
+            lifted_by: None,
+            lifted_at: None,
+            lift_reason: None,
+            expiry_attempts: 0,
         };
 
-        db.log_suspension(suspension).await.expect(format!("Failed to log suspension for {}", &user.name).as_str());
-
-        let config = &ctx.data().config;
+        let config = &Config::current();
         let guild_id = &ctx.guild_id().unwrap().get();
-        let guild_config = Config::get_guild_config(&config, *guild_id).unwrap();
+        let guild_config = Config::get_guild_config(config, *guild_id).unwrap();
         let suspended_role = guild_config.roles.suspended;
 
-        guild_member.remove_roles(&ctx, &guild_member.roles).await?;
-        guild_member.add_role(&ctx, suspended_role).await?;
+        // Stage the suspension first, if Discord refuses the role change it is ended with a discard event
+        let suspension_id = match db.log_suspension(suspension, ActionSource::Command).await {
+            Ok(suspension_id) => suspension_id,
+            Err(error) => {
+
+                // Another command suspended the user since the check above
+                if helper::is_duplicate_suspension(&error) && helper::user_is_suspended(&ctx, &user).await {
+                    return Ok(());
+                }
+
+                ctx.send(
+                    poise::CreateReply::default()
+                        .content(format!(":x: Could not save the suspension of {}, nothing was changed: {}", user.mention(), error))
+                        .ephemeral(true)
+                ).await?;
+
+                return Ok(());
+            }
+        };
+
+        tracing::Span::current().record("suspension_id", suspension_id);
+
+        // Replace all roles in a single request, so Discord applies all of it or nothing
+        if let Err(error) = guild_member.edit(&ctx, EditMember::new().roles([RoleId::new(suspended_role)])).await {
+
+            // The attempt stays in the history, ended with the reason it failed
+            let discarded = db.set_suspension_inactive(SuspensionEvent {
+                suspension_id,
+                action: SuspensionAction::Discard,
+                actor_id: Some(ctx.author().id.get() as i64),
+                created_at: Utc::now(),
+                reason: Some(format!("Discord did not apply it: {}", error)),
+                source: ActionSource::Command,
+            }).await;
+
+            let content = match discarded {
+                Ok(()) => format!(":x: Could not suspend {}, nothing was changed: {}", user.mention(), error),
+                Err(discard_error) => {
+                    error!(suspension_id, error = %discard_error, "Failed to discard suspension that could not be applied");
+                    format!(":x: Could not suspend {}: {}\r\nThe suspension is still saved though, remove it with `/remove_suspension`!", user.mention(), error)
+                }
+            };
+
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(content)
+                    .ephemeral(true)
+            ).await?;
+
+            return Ok(());
+        }
+
+        ctx.data().schedule_changed.notify_one();
+        info!(%until, "Suspension applied");
 
         // Get the log channel id's from guild config
         let log_channel_id = guild_config.channels.ban_log;
@@ -96,12 +146,12 @@ pub async fn suspend(
             // Send a message
             tuple.1.send_message(&ctx, CreateMessage::default().content(
                 format!("### Suspension Log\r\nName: {}\r\nReason: **{}**\r\nUntil: {}",
-                user.mention(), &reason_string, helper::date_string_to_discord_timestamp(until_string)),
+                user.mention(), &reason_string, helper::datetime_to_discord_timestamp(&until)),
             )).await?;
 
         } else {
-            let guild_name = &ctx.guild_id().unwrap().name(&ctx).unwrap();
-            println!("Unable to find log channel for guild {} ({})", guild_name, guild_id);
+            let guild_name = &ctx.guild_id().unwrap().name(ctx).unwrap();
+            warn!(guild_name, "Unable to find log channel");
         }
 
         // Send embed to staff log channel with more information
@@ -117,18 +167,18 @@ pub async fn suspend(
                 .color(serenity::Colour::DARK_RED)
                 .field("User", user.mention().to_string(), false)
                 .field("Issued by", author_member.mention().to_string(), false)
-                .field("Until", helper::date_string_to_discord_timestamp(until_string), false)
+                .field("Until", helper::datetime_to_discord_timestamp(&until), false)
                 .field("Reason", &reason_string, true)
                 .field("Removed roles", role_mentions.join(", ").as_str(), true);
 
             // Send the embed
             tuple.1.send_message(&ctx, CreateMessage::default().embed(embed)).await?;
         } else {
-            let guild_name = &ctx.guild_id().unwrap().name(&ctx).unwrap();
-            println!("Unable to find staff log channel for guild {} ({})", guild_name, guild_id);
+            let guild_name = &ctx.guild_id().unwrap().name(ctx).unwrap();
+            warn!(guild_name, "Unable to find staff log channel");
         }
         
-        ctx.reply(format!(":hammer: {} has been suspended until {}!", user.mention(), helper::date_string_to_discord_timestamp(until_string))).await?;
+        ctx.reply(format!(":hammer: {} has been suspended until {}!", user.mention(), helper::datetime_to_discord_timestamp(&until))).await?;
         
         Ok(())
     } else {
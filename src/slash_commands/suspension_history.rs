@@ -1,10 +1,15 @@
 use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::Mentionable;
 use crate::{Context, Error};
+use crate::db::SuspensionEvent;
 use crate::helper;
 
+// Long histories go into follow-up messages, up to this many in total
+const MAX_MESSAGES: usize = 5;
+
 /// Returns the history of suspensions for a user
 #[poise::command(slash_command)]
+#[tracing::instrument(name = "command", skip_all, fields(command = "suspension_history", guild_id = ctx.guild_id().map(|guild_id| guild_id.get()), moderator_id = %ctx.author().id, user_id = %user.id))]
 pub async fn suspension_history(
     ctx: Context<'_>,
     #[description = "Selected user"] user: serenity::User,
@@ -20,31 +25,75 @@ pub async fn suspension_history(
     let guild_id = ctx.guild_id().unwrap().get();
     let suspensions = db.get_suspensions(guild_id as i64, user.id.get() as i64).await?;
 
-    let mut message = format!("## :open_file_folder: Suspension history for {}\r\n", user.mention());
-    let mut count = 1;
+    let mut header = format!("## :open_file_folder: Suspension history for {}\r\n", user.mention());
 
-    if suspensions.len() == 0 {
-        message = format!(":sparkles: {} has never been suspended. What a good boy/girl!", user.mention());
+    if suspensions.is_empty() {
+        header = format!(":sparkles: {} has never been suspended. What a good boy/girl!", user.mention());
     }
 
-    for suspension in suspensions {
+    let mut blocks = vec![];
+
+    for (count, suspension) in (1..).zip(suspensions) {
         
-        message += format!("\r\n### {count}. Suspension {}\r\nIssued by: {}\r\nFrom: {}\r\nUntil: {}\r\nReason: {}",
-                            { if suspension.active.unwrap_or_else(|| false) {"(Active)"} else {""} },
-                            ctx.guild_id().unwrap().member(ctx, suspension.moderator_id as u64).await?.mention(),
-                            helper::date_string_to_discord_timestamp(&suspension.from_datetime),
-                            helper::date_string_to_discord_timestamp(&suspension.until_datetime),
-                            suspension.reason.as_deref().unwrap_or("None")
-        ).as_str();
-
-        count += 1;
+        // Show the role names from the time of removal, the roles might not exist anymore
+        let removed_roles: Vec<String> = suspension.removed_roles.iter()
+            .map(|role| role.role_name.clone().unwrap_or_else(|| format!("<@&{}>", role.role_id)))
+            .collect();
+
+        // Mention by id, the moderator may have left, been imported or been forgotten
+        let mut message = format!("\r\n### {count}. Suspension {}\r\nIssued by: <@{}>\r\nFrom: {}\r\nUntil: {}\r\nReason: {}\r\nRemoved roles: {}",
+                            { if suspension.active.unwrap_or(false) {"(Active)"} else {""} },
+                            suspension.moderator_id,
+                            helper::datetime_to_discord_timestamp(&suspension.from_datetime),
+                            helper::datetime_to_discord_timestamp(&suspension.until_datetime),
+                            suspension.reason.as_deref().unwrap_or("None"),
+                            if removed_roles.is_empty() { String::from("None") } else { removed_roles.join(", ") }
+        );
+
+        if let Some(lifted_by) = suspension.lifted_by {
+            message += format!("\r\nLifted early by <@{}>", lifted_by).as_str();
+
+            if let Some(lifted_at) = &suspension.lifted_at {
+                message += format!(" on {}", helper::datetime_to_discord_timestamp(lifted_at)).as_str();
+            }
+            if let Some(lift_reason) = &suspension.lift_reason {
+                message += format!(": {}", lift_reason).as_str();
+            }
+        }
+
+        // List everything that happened to the suspension
+        for event in db.get_events(suspension.id).await? {
+            message += format!("\r\n- {}", format_event(&event)).as_str();
+        }
+
+        blocks.push(message);
+    }
+
+    // Every suspension stays in one piece, the first message gets the reply and the rest follow up
+    for message in helper::paginate(header, blocks, MAX_MESSAGES, "suspension(s)") {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(message)
+                .ephemeral(true)
+        ).await?;
     }
-    
-    ctx.send(
-        poise::CreateReply::default()
-            .content(message)
-            .ephemeral(true)
-    ).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+// Format an event log entry as a single line
+fn format_event(event: &SuspensionEvent) -> String {
+
+    let mut line = format!("{} **{}**", helper::datetime_to_discord_timestamp(&event.created_at), event.action);
+
+    if let Some(actor_id) = event.actor_id {
+        line += format!(" by <@{}>", actor_id).as_str();
+    }
+
+    line += format!(" ({})", event.source).as_str();
+
+    if let Some(reason) = &event.reason {
+        line += format!(": {}", reason).as_str();
+    }
+
+    line
+}
@@ -1,63 +1,218 @@
-use chrono::Local;
+use chrono::{DateTime, Duration, Utc};
 use poise::serenity_prelude as serenity;
-use poise::serenity_prelude::{GuildId, Http, Mentionable, UserId};
-use sqlx::{Row, SqlitePool};
+use poise::serenity_prelude::{ChannelId, CreateMessage, GuildId, Http, Mentionable, UserId};
+use tokio::sync::Notify;
 use tokio::time::{sleep_until, Instant};
-use crate::config::Config;
-use crate::db::{Database, Suspension};
-use crate::helper::restore_roles;
+use tracing::{error, info, info_span, warn, Instrument};
+use crate::config::{Config, RetentionMode};
+use crate::db::{ActionSource, Suspension, SuspensionAction, SuspensionEvent, SuspensionRepository};
+use crate::helper::{datetime_to_discord_timestamp, is_unknown_member, restore_roles};
+use crate::metrics;
+use crate::reminders::send_due_reminders;
+use crate::shutdown::Shutdown;
+use crate::Error;
 
-pub async fn start_monitoring(pool: &SqlitePool, http: &Http, config: &Config, db: &Database) {
+// Failed expiries are retried after 30 seconds, doubling up to an hour
+const FIRST_RETRY_IN_SECONDS: i64 = 30;
+const MAX_RETRY_IN_SECONDS: i64 = 60 * 60;
+
+// Staff is told about every third failed attempt
+const REPORT_EVERY_ATTEMPTS: i32 = 3;
+
+pub async fn start_monitoring(http: &Http, db: &dyn SuspensionRepository, schedule_changed: &Notify, shutdown: &Shutdown) {
 
     loop {
 
+        metrics::monitor_heartbeat();
+
+        // Pick up a reloaded config on every round
+        let config = &Config::current();
+
         // Check expired suspensions after waking up
-        let expired_suspensions = sqlx::query("SELECT * FROM suspensions WHERE until_datetime <= ? AND active = TRUE")
-            .bind(Local::now().naive_local().format("%Y-%m-%d %H:%M:%S").to_string())
-            .fetch_all(pool)
+        let expired_suspensions = db.get_expired_suspensions(Utc::now())
             .await
-            .unwrap_or_else(|_| vec![]);
-
-        for row in expired_suspensions {
-            let suspension = Suspension {
-                id: row.get("id"),
-                guild_id: row.get("guild_id"),
-                user_id: row.get("user_id"),
-                moderator_id: row.get("moderator_id"),
-                previous_roles: row.get::<String, _>("previous_roles").split(',').map(String::from).collect(),
-                from_datetime: row.get("from_datetime"),
-                until_datetime: row.get("until_datetime"),
-                reason: row.get("reason"),
-                active: row.get("active"),
+            .unwrap_or_else(|error| {
+                error!(%error, "Failed to get expired suspensions");
+                vec![]
+            });
+
+        // Every suspension on its own, one failure must not stop the others
+        for suspension in expired_suspensions {
+
+            // Leave the rest for after the restart
+            let Some(_in_flight) = shutdown.begin() else {
+                break;
             };
 
-            let guild = http.get_guild(GuildId::new(suspension.guild_id as u64)).await.unwrap();
-            let guild_id = guild.id;
-            let guild_config = Config::get_guild_config(&config, guild_id.get()).unwrap();
-            let log_channel_id = guild_config.channels.ban_log;
-            let suspended_role_id = guild_config.roles.suspended;
-
-            // Try to restore roles
-            restore_roles(&http, guild_id, suspended_role_id, &suspension).await.expect(format!("Unable to restore roles for user id {}", suspension.user_id).as_str());
-
-            // Set suspension inactive
-            db.set_suspension_inactive(suspension.id).await;
-            
-            // Try to get the public log channel
-            if let Some(tuple) = guild.channels(&http).await.unwrap().iter().find(|tuple| {*tuple.0 == log_channel_id}) {
-                
-                let member_id = UserId::new(suspension.user_id as u64);
-                let member = guild.member(&http, member_id).await
-                    .expect(&format!("Failed to get member ({}) from guild {}", member_id, guild.name));
-
-                // Send a message
-                tuple.1.send_message(&http, serenity::CreateMessage::default().content(format!("### Suspension expired\r\n{}", member.mention()))).await
-                    .expect(&format!("Failed to send message to log-channel of guild {}", guild.name));
-            } else {
-                println!("Unable to find log channel for guild {} ({})", guild.name, guild_id);
-            }
+            let span = info_span!("expiry", suspension_id = suspension.id, guild_id = suspension.guild_id, user_id = suspension.user_id);
+
+            async {
+                match expire_suspension(http, config, db, &suspension).await {
+                    Ok(()) => {
+                        info!("Suspension expired");
+                        metrics::suspension_expired(suspension.until_datetime);
+                    }
+                    Err(error) => handle_expiry_failure(http, config, db, &suspension, error).await,
+                }
+            }.instrument(span).await;
+        }
+
+        apply_retention(config, db).await;
+
+        let next_reminder = send_due_reminders(http, config, db).await;
+
+        // Sleep until the next suspension ends, the next reminder is due or the schedule changes
+        let wake_up = next_wake_up(config, db, next_reminder).await;
+
+        tokio::select! {
+            _ = sleep_until(wake_up) => {}
+            _ = schedule_changed.notified() => {}
+        }
+    }
+}
+
+async fn expire_suspension(http: &Http, config: &Config, db: &dyn SuspensionRepository, suspension: &Suspension) -> Result<(), Error> {
+
+    let guild_id = GuildId::new(suspension.guild_id as u64);
+    let guild_config = Config::get_guild_config(config, guild_id.get())
+        .ok_or_else(|| format!("Guild {} is not configured", guild_id))?;
+
+    // Members who left can't get their roles back, the suspension ends anyway
+    let reason = match restore_roles(http, guild_id, guild_config.roles.suspended, suspension).await {
+        Ok(_) => None,
+        Err(error) if is_unknown_member(&error) => Some(String::from("Member is no longer on the server, roles were not restored")),
+        Err(error) => return Err(error),
+    };
+
+    // Set suspension inactive
+    db.set_suspension_inactive(SuspensionEvent {
+        suspension_id: suspension.id,
+        action: SuspensionAction::Expire,
+        actor_id: None,
+        created_at: Utc::now(),
+        reason,
+        source: ActionSource::Monitor,
+    }).await?;
+
+    // The suspension has ended, a missing log message is no reason to retry
+    let log_channel_id = ChannelId::new(guild_config.channels.ban_log);
+    let message = CreateMessage::default().content(format!("### Suspension expired\r\n{}", UserId::new(suspension.user_id as u64).mention()));
+
+    if let Err(error) = log_channel_id.send_message(http, message).await {
+        warn!(%error, "Failed to send message to log channel");
+    }
+
+    Ok(())
+}
+
+async fn handle_expiry_failure(http: &Http, config: &Config, db: &dyn SuspensionRepository, suspension: &Suspension, error: Error) {
+
+    let attempts = suspension.expiry_attempts + 1;
+    let next_attempt_at = Utc::now() + retry_delay(attempts);
+
+    warn!(attempts, %next_attempt_at, %error, "Failed to expire suspension, retrying later");
+
+    if error.downcast_ref::<serenity::Error>().is_some() {
+        metrics::discord_error("expiry");
+    }
+
+    if let Err(error) = db.record_expiry_failure(suspension.id, next_attempt_at).await {
+        error!(%error, "Failed to record failed expiry");
+    }
+
+    if attempts % REPORT_EVERY_ATTEMPTS != 0 {
+        return;
+    }
+
+    let Some(guild_config) = Config::get_guild_config(config, suspension.guild_id as u64) else {
+        return;
+    };
+
+    let embed = serenity::CreateEmbed::default()
+        .title("Suspension could not be ended")
+        .color(serenity::Colour::ORANGE)
+        .field("User", UserId::new(suspension.user_id as u64).mention().to_string(), false)
+        .field("Was until", datetime_to_discord_timestamp(&suspension.until_datetime), false)
+        .field("Attempts", attempts.to_string(), true)
+        .field("Next attempt", datetime_to_discord_timestamp(&next_attempt_at), true)
+        .field("Error", error.to_string(), false);
+
+    let staff_log_channel_id = ChannelId::new(guild_config.channels.ban_log_staff);
+
+    if let Err(error) = staff_log_channel_id.send_message(http, CreateMessage::default().embed(embed)).await {
+        warn!(%error, "Failed to send message to staff log channel");
+    }
+}
+
+// Exponential backoff, starting at FIRST_RETRY_IN_SECONDS for the first failure
+fn retry_delay(attempts: i32) -> Duration {
+
+    let factor = 2_i64.saturating_pow(attempts.saturating_sub(1).clamp(0, 16) as u32);
+
+    Duration::seconds(FIRST_RETRY_IN_SECONDS.saturating_mul(factor).min(MAX_RETRY_IN_SECONDS))
+}
+
+// The monitoring interval is only a fallback for changes made without a command, like imports
+async fn next_wake_up(config: &Config, db: &dyn SuspensionRepository, next_reminder: Option<DateTime<Utc>>) -> Instant {
+
+    let now = Instant::now();
+    let fallback = now + std::time::Duration::from_secs(config.monitoring_interval_in_seconds);
+
+    let next_expiry = db.get_next_expiry().await.unwrap_or_else(|error| {
+        error!(%error, "Failed to get the next expiry");
+        None
+    });
+
+    let next = match (next_expiry, next_reminder) {
+        (Some(expiry), Some(reminder)) => Some(expiry.min(reminder)),
+        (expiry, reminder) => expiry.or(reminder),
+    };
+
+    match next {
+        Some(due) => match (due - Utc::now()).to_std() {
+            Ok(remaining) => fallback.min(now + remaining),
+            // Already due but still active, don't spin on it
+            Err(_) => fallback.min(now + std::time::Duration::from_secs(1)),
+        },
+        None => fallback,
+    }
+}
+
+// Purge or anonymize ended suspensions of guilds with a retention policy
+async fn apply_retention(config: &Config, db: &dyn SuspensionRepository) {
+
+    for guild_config in &config.guilds {
+
+        let Some(retention) = &guild_config.retention else {
+            continue;
+        };
+
+        let guild_id = guild_config.id as i64;
+        let before = Utc::now() - Duration::days(retention.keep_for_days.into());
+
+        let (result, verb) = match retention.mode {
+            RetentionMode::Purge => (db.purge_suspensions(guild_id, before).await, "Purged"),
+            RetentionMode::Anonymize => (db.anonymize_suspensions(guild_id, before).await, "Anonymized"),
+        };
+
+        match result {
+            Ok(0) => {}
+            Ok(count) => info!(guild_id, count, %before, "{} suspensions that ended before the retention period", verb),
+            Err(error) => error!(guild_id, %error, "Failed to apply retention policy"),
         }
-        
-        sleep_until(Instant::now() + std::time::Duration::from_secs(config.monitoring_interval_in_seconds)).await;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::seconds(30));
+        assert_eq!(retry_delay(2), Duration::seconds(60));
+        assert_eq!(retry_delay(4), Duration::seconds(240));
+        assert_eq!(retry_delay(8), Duration::hours(1));
+        assert_eq!(retry_delay(i32::MAX), Duration::hours(1));
+    }
+}